@@ -1,3 +1,39 @@
+use std::path::Path;
+
+/// The fonts the `embed-cjk-fonts` feature bundles, see their README.
+const CJK_FONTS: &[&str] = &[
+    "NotoSerifSC-Regular.otf",
+    "NotoSerifSC-Bold.otf",
+    "NotoSansSC-Regular.otf",
+    "NotoSansSC-Bold.otf",
+];
+
 fn main() {
+    if std::env::var_os("CARGO_FEATURE_EMBED_CJK_FONTS").is_some() {
+        check_cjk_fonts();
+    }
     tauri_build::build()
 }
+
+/// Fail with the download instructions instead of a bare `include_bytes!`
+/// error when the CJK fonts were not downloaded.
+fn check_cjk_fonts() {
+    let dir = Path::new("assets/fonts/cjk");
+    let missing: Vec<&str> = CJK_FONTS
+        .iter()
+        .copied()
+        .filter(|font| {
+            let path = dir.join(font);
+            println!("cargo:rerun-if-changed={}", path.display());
+            !path.is_file()
+        })
+        .collect();
+    if !missing.is_empty() {
+        let readme = std::fs::read_to_string(dir.join("README.md")).unwrap_or_default();
+        panic!(
+            "the embed-cjk-fonts feature needs {} in {}\n\n{readme}",
+            missing.join(", "),
+            dir.display(),
+        );
+    }
+}
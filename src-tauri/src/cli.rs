@@ -0,0 +1,330 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::engine::{
+    check_ppi, export_images, export_pdf, find_font_issues, parse_pages, DependencyWatcher,
+    ExportFormat, FontIssue, FontIssueKind, NoleWorld, TypstDiagnostic, TypstDiagnosticSeverity,
+    TypstEngine, DEFAULT_PPI,
+};
+use crate::error::{NoleError, NoleResult};
+
+/// The separator of font paths given at once, like in `PATH`.
+const ENV_PATH_SEP: char = if cfg!(windows) { ';' } else { ':' };
+
+/// How long `watch` lets a burst of file changes settle before recompiling.
+const SETTLE_DELAY: Duration = Duration::from_millis(50);
+
+/// Render notes without opening a window.
+#[derive(Parser, Debug)]
+#[command(name = "nole", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compile a file to pdf.
+    Compile(CompileArgs),
+    /// Compile a file to pdf whenever it or the files it uses change.
+    Watch(CompileArgs),
+    /// Export a file as pdf or images.
+    Export(ExportArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CompileArgs {
+    /// The file to compile.
+    pub input: PathBuf,
+    /// The pdf to write, next to the input by default.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// The workspace root, the directory of the input by default.
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+    /// Font directories searched before the system fonts, instead of
+    /// `NOLE_FONT_PATHS`.
+    #[arg(long = "font-path", value_delimiter = ENV_PATH_SEP)]
+    pub font_paths: Vec<PathBuf>,
+    /// Warn about text that rendered as boxes or with a fallback font.
+    #[arg(long)]
+    pub check_fonts: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ExportArgs {
+    /// The file to export.
+    pub input: PathBuf,
+    /// The pdf to write, or the directory images are written into.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// The workspace root, the directory of the input by default.
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+    /// Font directories searched before the system fonts, instead of
+    /// `NOLE_FONT_PATHS`.
+    #[arg(long = "font-path", value_delimiter = ENV_PATH_SEP)]
+    pub font_paths: Vec<PathBuf>,
+    /// Warn about text that rendered as boxes or with a fallback font.
+    #[arg(long)]
+    pub check_fonts: bool,
+    #[arg(short, long, value_enum, default_value_t = CliFormat::Pdf)]
+    pub format: CliFormat,
+    /// The pages to export as images, like `1-3,7`. All pages by default.
+    #[arg(long)]
+    pub pages: Option<String>,
+    /// The pixels per inch of png images.
+    #[arg(long, default_value_t = DEFAULT_PPI, value_parser = parse_ppi)]
+    pub ppi: f32,
+    /// The file names of images, supports `{stem}`, `{page}` and `{total}`.
+    #[arg(long)]
+    pub template: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum CliFormat {
+    Pdf,
+    Png,
+    Svg,
+}
+
+impl From<CliFormat> for ExportFormat {
+    fn from(format: CliFormat) -> Self {
+        match format {
+            CliFormat::Pdf => Self::Pdf,
+            CliFormat::Png => Self::Png,
+            CliFormat::Svg => Self::Svg,
+        }
+    }
+}
+
+/// Parse the pixels per inch of png images.
+fn parse_ppi(value: &str) -> Result<f32, String> {
+    let ppi = value.parse::<f32>().map_err(|err| err.to_string())?;
+    check_ppi(ppi).map_err(|err| err.to_string())?;
+    Ok(ppi)
+}
+
+/// The subcommands that run without a window.
+const COMMANDS: &[&str] = &["compile", "watch", "export", "help", "--help", "-h", "--version", "-V"];
+
+/// Whether the arguments ask for the command line instead of the app.
+pub fn requested() -> bool {
+    std::env::args()
+        .nth(1)
+        .is_some_and(|arg| COMMANDS.contains(&arg.as_str()))
+}
+
+/// Print to the terminal nole was started from.
+///
+/// Release builds on Windows are GUI applications without a console of their
+/// own, so the output of the command line would be lost.
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // Safety: Only attaches the standard streams, fails if there is no parent
+    // console, e.g. when started from the explorer.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+/// Run the command line.
+pub fn run() -> ExitCode {
+    attach_console();
+    let cli = Cli::parse();
+    let engine = TypstEngine::new();
+    let font_paths = match &cli.command {
+        Command::Compile(args) | Command::Watch(args) => &args.font_paths,
+        Command::Export(args) => &args.font_paths,
+    };
+    if !font_paths.is_empty() {
+        if let Err(err) = engine.set_font_dirs(font_paths.clone()) {
+            print_error(&err);
+            return ExitCode::FAILURE;
+        }
+    }
+    let result = match cli.command {
+        Command::Compile(args) => compile(&engine, &args),
+        Command::Watch(args) => watch(&engine, &args),
+        Command::Export(args) => export(&engine, &args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            print_error(&err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Compile the input once.
+fn compile(engine: &TypstEngine, args: &CompileArgs) -> NoleResult<()> {
+    let root = root(&args.input, &args.root);
+    let world = NoleWorld::new(root.clone(), args.input.clone(), engine.core(&root)?)?;
+    compile_world(&world, args)
+}
+
+/// Compile the input again whenever a file it uses changes.
+fn watch(engine: &TypstEngine, args: &CompileArgs) -> NoleResult<()> {
+    let root = root(&args.input, &args.root);
+    let mut world = NoleWorld::new(root.clone(), args.input.clone(), engine.core(&root)?)?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = DependencyWatcher::new(move |_| {
+        let _ = sender.send(());
+    })?;
+    loop {
+        world.reset();
+        if let Err(err) = compile_world(&world, args) {
+            print_error(&err);
+        }
+        comemo::evict(10);
+        watcher.update(world.dependencies());
+
+        if receiver.recv().is_err() {
+            return Ok(());
+        }
+        thread::sleep(SETTLE_DELAY);
+        while receiver.try_recv().is_ok() {}
+    }
+}
+
+/// Compile a world and write the pdf.
+fn compile_world(world: &NoleWorld, args: &CompileArgs) -> NoleResult<()> {
+    let start = std::time::Instant::now();
+    let (document, warnings) = world.compile()?;
+    print_diagnostics(&warnings);
+    if args.check_fonts {
+        print_font_issues(&find_font_issues(world, &document));
+    }
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.input.with_extension("pdf"));
+    export_pdf(&document, &args.input.to_string_lossy(), &output)?;
+    eprintln!("compiled {} in {:?}", output.display(), start.elapsed());
+    Ok(())
+}
+
+/// Export the input as pdf or images.
+fn export(engine: &TypstEngine, args: &ExportArgs) -> NoleResult<()> {
+    let root = root(&args.input, &args.root);
+    let world = NoleWorld::new(root.clone(), args.input.clone(), engine.core(&root)?)?;
+    let (document, warnings) = world.compile()?;
+    print_diagnostics(&warnings);
+    if args.check_fonts {
+        print_font_issues(&find_font_issues(&world, &document));
+    }
+
+    let format = ExportFormat::from(args.format);
+    if format == ExportFormat::Pdf {
+        let output = args
+            .output
+            .clone()
+            .unwrap_or_else(|| args.input.with_extension("pdf"));
+        return export_pdf(&document, &args.input.to_string_lossy(), &output);
+    }
+
+    let dir = match &args.output {
+        Some(dir) => dir.clone(),
+        None => args
+            .input
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    };
+    let stem = args
+        .input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "page".to_string());
+    let pages = parse_pages(args.pages.as_deref().unwrap_or_default(), document.pages.len())?;
+    let template = args.template.clone().unwrap_or_else(|| format.template());
+    for path in export_images(&document, format, &pages, args.ppi, &dir, &template, &stem)? {
+        eprintln!("exported {}", path.display());
+    }
+    Ok(())
+}
+
+/// The workspace root of the input.
+fn root(input: &Path, root: &Option<PathBuf>) -> PathBuf {
+    root.clone()
+        .or_else(|| input.parent().map(Path::to_path_buf))
+        .filter(|root| !root.as_os_str().is_empty())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Print an error, including the diagnostics of a failed compilation.
+fn print_error(err: &NoleError) {
+    if let NoleError::CompileFailed(diagnostics) = err {
+        print_diagnostics(diagnostics);
+    }
+    eprintln!("error: {err}");
+}
+
+/// Print diagnostics like `error: chapter.typ:3:5: unknown variable`.
+fn print_diagnostics(diagnostics: &[TypstDiagnostic]) {
+    for diagnostic in diagnostics {
+        let severity = match diagnostic.severity {
+            TypstDiagnosticSeverity::Error => "error",
+            TypstDiagnosticSeverity::Warning => "warning",
+        };
+        let location = &diagnostic.location;
+        match &location.path {
+            Some(path) => eprintln!(
+                "{severity}: {path}:{}:{}: {}",
+                location.line + 1,
+                location.column + 1,
+                diagnostic.message
+            ),
+            None => eprintln!("{severity}: {}", diagnostic.message),
+        }
+        for point in &diagnostic.trace {
+            let location = &point.location;
+            eprintln!(
+                "  {} at {}:{}:{}",
+                point.message,
+                location.path.as_deref().unwrap_or("<unknown>"),
+                location.line + 1,
+                location.column + 1
+            );
+        }
+        for hint in &diagnostic.hints {
+            eprintln!("  hint: {hint}");
+        }
+    }
+}
+
+/// Print text that rendered as boxes or with a fallback font as warnings.
+fn print_font_issues(issues: &[FontIssue]) {
+    for issue in issues {
+        let message = match (issue.kind, &issue.requested) {
+            (FontIssueKind::Missing, _) => {
+                format!("no font has glyphs for `{}`, tried {}", issue.text, issue.font)
+            }
+            (FontIssueKind::Fallback, Some(requested)) => {
+                format!("`{}` fell back from {requested} to {}", issue.text, issue.font)
+            }
+            (FontIssueKind::Fallback, None) => {
+                format!("`{}` fell back to {}", issue.text, issue.font)
+            }
+        };
+        let location = &issue.location;
+        match &location.path {
+            Some(path) => eprintln!(
+                "warning: {path}:{}:{}: {message}",
+                location.line + 1,
+                location.column + 1
+            ),
+            None => eprintln!("warning: page {}: {message}", issue.page),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use super::{export_pdf, NoleWorld, TypstCore};
+use crate::error::{NoleError, NoleResult};
+
+/// The files exported when no include globs are given.
+pub const DEFAULT_INCLUDE: &str = "**/*.typ";
+
+/// The state of one file of a batch export.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchState {
+    Done,
+    Failed,
+}
+
+/// Progress of a batch export, reported once per file.
+#[derive(Serialize, Clone, Debug)]
+pub struct BatchProgress {
+    /// The workspace-relative path of the file.
+    pub path: PathBuf,
+    /// The written pdf, if the export succeeded.
+    pub output: Option<PathBuf>,
+    pub state: BatchState,
+    pub error: Option<NoleError>,
+    /// The number of files finished so far, including this one.
+    pub finished: usize,
+    pub total: usize,
+}
+
+/// The outcome of a batch export.
+#[derive(Serialize, Clone, Debug)]
+pub struct BatchSummary {
+    pub exported: Vec<PathBuf>,
+    pub failed: Vec<PathBuf>,
+}
+
+/// Find the files of the workspace matching the globs, relative to it.
+///
+/// Hidden files and directories, like the `.nole` directory, are skipped.
+pub fn find_entries(
+    workspace: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> NoleResult<Vec<PathBuf>> {
+    let include = if include.is_empty() {
+        globs(&[DEFAULT_INCLUDE.to_string()])?
+    } else {
+        globs(include)?
+    };
+    let exclude = globs(exclude)?;
+
+    let mut entries: Vec<PathBuf> = WalkDir::new(workspace)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.path().strip_prefix(workspace).ok().map(Path::to_path_buf))
+        .filter(|path| include.is_match(path) && !exclude.is_match(path))
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+/// Compile the files of the workspace in parallel and write a pdf for each.
+///
+/// Each file is compiled in its own world. The pdfs keep the directory layout
+/// of the workspace below `out_dir`. `report` is called once a file is done.
+pub fn export_batch(
+    core: Arc<TypstCore>,
+    workspace: &Path,
+    out_dir: &Path,
+    entries: &[PathBuf],
+    report: impl Fn(BatchProgress) + Sync,
+) -> BatchSummary {
+    let next = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(entries.len().max(1));
+
+    let results: Vec<(PathBuf, bool)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let Some(path) = entries.get(next.fetch_add(1, Ordering::SeqCst)) else {
+                            break;
+                        };
+                        let output = out_dir.join(path).with_extension("pdf");
+                        // A panic in typst fails the file instead of its worker.
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            export_one(core.clone(), workspace, path, &output)
+                        }))
+                        .unwrap_or_else(|payload| Err(NoleError::panicked(payload)));
+                        results.push((path.clone(), result.is_ok()));
+                        report(BatchProgress {
+                            path: path.clone(),
+                            output: result.is_ok().then(|| output.clone()),
+                            state: if result.is_ok() { BatchState::Done } else { BatchState::Failed },
+                            error: result.err(),
+                            finished: finished.fetch_add(1, Ordering::SeqCst) + 1,
+                            total: entries.len(),
+                        });
+                    }
+                    results
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    });
+
+    let (exported, failed): (Vec<_>, Vec<_>) = results.into_iter().partition(|(_, ok)| *ok);
+    BatchSummary {
+        exported: exported.into_iter().map(|(path, _)| path).collect(),
+        failed: failed.into_iter().map(|(path, _)| path).collect(),
+    }
+}
+
+/// Compile one file of the workspace and write it as pdf.
+fn export_one(core: Arc<TypstCore>, workspace: &Path, path: &Path, output: &Path) -> NoleResult<()> {
+    let world = NoleWorld::new(workspace.to_path_buf(), workspace.join(path), core)?;
+    let (document, _) = world.compile()?;
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    export_pdf(&document, &path.to_string_lossy(), output)
+}
+
+/// Build a set of globs.
+fn globs(patterns: &[String]) -> NoleResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|err| NoleError::Other(format!("invalid glob `{pattern}`: {err}").into()))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|err| NoleError::Other(err.to_string().into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A workspace with some notes, a hidden directory and an image.
+    fn workspace() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nole-batch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for file in [
+            "main.typ",
+            "chapters/intro.typ",
+            "chapters/draft/idea.typ",
+            ".nole/packages/local/lib/0.1.0/lib.typ",
+            ".hidden.typ",
+            "images/figure.png",
+        ] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_find_entries() {
+        let dir = workspace();
+        let strings = |globs: &[&str]| -> Vec<String> {
+            globs.iter().map(|glob| glob.to_string()).collect()
+        };
+        let paths = |include: &[&str], exclude: &[&str]| {
+            find_entries(&dir, &strings(include), &strings(exclude)).unwrap()
+        };
+
+        // All `.typ` files by default, without hidden files and directories.
+        assert_eq!(
+            paths(&[], &[]),
+            [
+                PathBuf::from("chapters/draft/idea.typ"),
+                PathBuf::from("chapters/intro.typ"),
+                PathBuf::from("main.typ"),
+            ]
+        );
+        assert_eq!(
+            paths(&["chapters/*.typ"], &["**/draft/**"]),
+            [PathBuf::from("chapters/intro.typ")]
+        );
+        assert_eq!(paths(&["**/*.png"], &[]), [PathBuf::from("images/figure.png")]);
+        assert!(find_entries(&dir, &["[".to_string()], &[]).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
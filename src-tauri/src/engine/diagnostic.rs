@@ -0,0 +1,114 @@
+use serde::Serialize;
+use std::ops::Range;
+use typst::diag::{Severity, SourceDiagnostic};
+use typst::syntax::{FileId, Source, Span};
+use typst::World;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TypstDiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+/// A location in a source file of the document.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct TypstLocation {
+    /// The workspace-relative path, prefixed with the package spec for files
+    /// in packages. None if the location is unknown.
+    pub path: Option<String>,
+    /// The char range in the file.
+    pub range: Range<usize>,
+    /// The zero-based line of the start.
+    pub line: usize,
+    /// The zero-based char column of the start.
+    pub column: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TypstTracepoint {
+    pub message: String,
+    #[serde(flatten)]
+    pub location: TypstLocation,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TypstDiagnostic {
+    #[serde(flatten)]
+    pub location: TypstLocation,
+    pub severity: TypstDiagnosticSeverity,
+    pub message: String,
+    pub hints: Vec<String>,
+    /// The chain of calls and imports leading to the diagnostic, innermost first.
+    pub trace: Vec<TypstTracepoint>,
+}
+
+impl TypstLocation {
+    /// Locate a span in the sources of the world.
+    pub fn new(world: &dyn World, span: Span) -> Self {
+        let Some(id) = span.id() else {
+            return Self::default();
+        };
+        Self::locate(world, id, |source| Some(source.find(span)?.range()))
+    }
+
+    /// Locate a byte range in a source file of the world.
+    pub fn from_range(world: &dyn World, id: FileId, range: Range<usize>) -> Self {
+        Self::locate(world, id, |source| source.text().get(range.clone()).map(|_| range))
+    }
+
+    /// Locate the byte range `find` gives in a source file of the world.
+    fn locate(
+        world: &dyn World,
+        id: FileId,
+        find: impl FnOnce(&Source) -> Option<Range<usize>>,
+    ) -> Self {
+        let path = display_path(id);
+        let location = world.source(id).ok().and_then(|source| {
+            let range = find(&source)?;
+            let text = source.text();
+            let start = text[..range.start].chars().count();
+            let size = text[range.start..range.end].chars().count();
+            Some((
+                start..start + size,
+                source.byte_to_line(range.start)?,
+                source.byte_to_column(range.start)?,
+            ))
+        });
+        match location {
+            Some((range, line, column)) => Self { path: Some(path), range, line, column },
+            None => Self { path: Some(path), ..Self::default() },
+        }
+    }
+}
+
+impl TypstDiagnostic {
+    pub fn new(world: &dyn World, diagnostic: &SourceDiagnostic) -> Self {
+        Self {
+            location: TypstLocation::new(world, diagnostic.span),
+            severity: match diagnostic.severity {
+                Severity::Error => TypstDiagnosticSeverity::Error,
+                Severity::Warning => TypstDiagnosticSeverity::Warning,
+            },
+            message: diagnostic.message.to_string(),
+            hints: diagnostic.hints.iter().map(|hint| hint.to_string()).collect(),
+            trace: diagnostic
+                .trace
+                .iter()
+                .map(|point| TypstTracepoint {
+                    message: point.v.to_string(),
+                    location: TypstLocation::new(world, point.span),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The workspace-relative path of a file, prefixed with the package spec for
+/// files in packages.
+pub fn display_path(id: FileId) -> String {
+    match id.package() {
+        Some(spec) => format!("{spec}/{}", id.vpath().as_rootless_path().display()),
+        None => id.vpath().as_rootless_path().display().to_string(),
+    }
+}
@@ -1,32 +1,256 @@
-use std::sync::{Mutex, Arc, RwLock};
-use crate::engine::{FontSearcher, FontSlot};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Mutex, Arc, PoisonError, RwLock};
+use crate::engine::{
+    font_families, CompileQueue, DependencyWatcher, FontCache, FontFamilyInfo, FontSearcher,
+    FontSlot, FontStats, PackageStorage, FONT_CAPACITY,
+};
 use comemo::Prehashed;
 use typst::Library;
-use typst::text::FontBook;
+use typst::text::{Font, FontBook};
 use typst::model::Document;
 
-use super::NoleWorld;
+use super::{NoleWorld, WorldSnapshot};
+use crate::error::{NoleError, NoleResult};
+
+/// The default main file of a workspace.
+pub const MAIN_FILE: &str = "main.typ";
+
+/// The directory of the fonts bundled with a workspace.
+pub const WORKSPACE_FONTS: &str = "fonts";
+
+/// The environment variable with global font directories, separated like `PATH`.
+pub const FONT_PATHS_ENV: &str = "NOLE_FONT_PATHS";
 
 pub struct TypstEngine {
-    /// Fonts and Typst's standard library.
-    pub core: Arc<TypstCore>,
+    /// Fonts and Typst's standard library, built on first use and keyed by
+    /// the font directories they were searched in.
+    cores: RwLock<HashMap<Vec<PathBuf>, Arc<TypstCore>>>,
+    /// Global font directories, searched after the workspace's fonts.
+    pub font_dirs: RwLock<Vec<PathBuf>>,
+    /// Downloaded and local packages.
+    pub packages: Arc<PackageStorage>,
     /// Last compiled document.
     pub document_cache: RwLock<Option<Document>>,
-    /// world of the typst.
+    /// world of the typst, only used by compilations.
     pub world_cache: Mutex<Option<NoleWorld>>,
+    /// The world of the last compilation, for queries of the editor.
+    pub snapshot: RwLock<Option<Arc<WorldSnapshot>>>,
+    /// Whether the next compilation comes from a newly opened editor, which
+    /// starts from a fresh world unless the entry stays the same.
+    pub init_requested: AtomicBool,
+    /// Pinned main files, keyed by workspace.
+    pub entries: RwLock<HashMap<PathBuf, PathBuf>>,
+    /// The source files the last compilation of each input used, keyed by the
+    /// input.
+    pub sources: RwLock<HashMap<PathBuf, HashSet<PathBuf>>>,
+    /// Runs the compilations of the editor in the background.
+    pub queue: CompileQueue,
+    /// Watches the files used by the last compilation.
+    pub watcher: Mutex<Option<DependencyWatcher>>,
+    /// Whether to recompile when a watched file changes.
+    pub auto_recompile: AtomicBool,
 }
 
 impl TypstEngine {
     pub fn new() -> Self {
+        let font_dirs = std::env::var_os(FONT_PATHS_ENV)
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
         Self { 
-            core: Arc::new(TypstCore::new()), 
+            cores: RwLock::new(HashMap::new()),
+            font_dirs: RwLock::new(font_dirs),
+            packages: Arc::new(PackageStorage::new()),
             document_cache: RwLock::new(None), 
-            world_cache: Mutex::new(None) 
+            world_cache: Mutex::new(None),
+            snapshot: RwLock::new(None),
+            init_requested: AtomicBool::new(false),
+            entries: RwLock::new(HashMap::new()),
+            sources: RwLock::new(HashMap::new()),
+            queue: CompileQueue::new(),
+            watcher: Mutex::new(None),
+            auto_recompile: AtomicBool::new(false),
+        }
+    }
+
+    /// Pin the main file of a workspace, or unpin it if `entry` is none.
+    pub fn set_entry(&self, workspace: PathBuf, entry: Option<PathBuf>) -> NoleResult<()> {
+        let mut entries = self.entries.write()?;
+        match entry {
+            Some(entry) => entries.insert(workspace, entry),
+            None => entries.remove(&workspace),
+        };
+        Ok(())
+    }
+
+    /// Get the core for a workspace.
+    ///
+    /// The fonts of the workspace take precedence over the global font
+    /// directories. Workspaces with the same font directories share a core,
+    /// so exporting another workspace does not search the fonts of the open
+    /// one again.
+    pub fn core(&self, workspace: &Path) -> NoleResult<Arc<TypstCore>> {
+        self.core_of(self.font_paths(Some(workspace))?)
+    }
+
+    /// Get the core of the workspace last compiled, or of the global fonts if
+    /// nothing was compiled yet.
+    pub fn current_core(&self) -> NoleResult<Arc<TypstCore>> {
+        match self.workspace()? {
+            Some(workspace) => self.core(&workspace),
+            None => self.core_of(self.font_paths(None)?),
+        }
+    }
+
+    /// Get the core searched in some font directories, searching them on
+    /// first use.
+    fn core_of(&self, font_paths: Vec<PathBuf>) -> NoleResult<Arc<TypstCore>> {
+        if let Some(core) = self.cores.read()?.get(&font_paths) {
+            return Ok(core.clone());
         }
+        let core = Arc::new(TypstCore::new(font_paths.clone(), self.packages.clone()));
+        // Another thread may have searched the same directories meanwhile.
+        let mut cores = self.cores.write()?;
+        Ok(cores.entry(font_paths).or_insert(core).clone())
+    }
+
+    /// The root of the world the editor compiles, if any.
+    fn workspace(&self) -> NoleResult<Option<PathBuf>> {
+        Ok(self.snapshot.read()?.as_ref().map(|snapshot| snapshot.root().to_path_buf()))
     }
-    pub fn reset(&self) -> Result<(), String> {
-        *self.world_cache.lock().map_err(|e|return "Get world lock faild!")? = None;
-        *self.document_cache.write().map_err(|e|return "Get document lock faild!")? = None;
+
+    /// Search the fonts again, e.g. after fonts were installed.
+    ///
+    /// Without a workspace, the fonts of the workspace last compiled are
+    /// searched, with the current global font directories. The other cached
+    /// cores are dropped, as the system fonts may have changed for them too.
+    /// The editor's world keeps its unsaved files and uses the new fonts from
+    /// the next compilation on, the caller compiles it again so the cached
+    /// document does not keep the old fonts. Returns the number of fonts found.
+    pub fn reload_fonts(&self, workspace: Option<&Path>) -> NoleResult<usize> {
+        let workspace = match workspace {
+            Some(workspace) => Some(workspace.to_path_buf()),
+            None => self.workspace()?,
+        };
+        self.cores.write()?.clear();
+        let core = self.core_of(self.font_paths(workspace.as_deref())?)?;
+        if let Some(world) = self.world_cache.lock()?.as_mut() {
+            let core = self.core(world.root())?;
+            world.set_core(core);
+        }
+        Ok(core.fonts.len())
+    }
+
+    /// The font directories of a workspace, by priority.
+    fn font_paths(&self, workspace: Option<&Path>) -> NoleResult<Vec<PathBuf>> {
+        let mut font_paths = vec![];
+        if let Some(workspace) = workspace {
+            // Key the cores the same for every spelling of the workspace.
+            let workspace = workspace.canonicalize().unwrap_or_else(|_| workspace.to_path_buf());
+            let fonts = workspace.join(WORKSPACE_FONTS);
+            if fonts.is_dir() {
+                font_paths.push(fonts);
+            }
+        }
+        font_paths.extend(self.font_dirs.read()?.iter().cloned());
+        Ok(font_paths)
+    }
+
+    /// Change the global font directories.
+    ///
+    /// The fonts are loaded by the next compilation. Fails without a change if
+    /// a directory does not exist.
+    pub fn set_font_dirs(&self, dirs: Vec<PathBuf>) -> NoleResult<()> {
+        if let Some(dir) = dirs.iter().find(|dir| !dir.is_dir()) {
+            return Err(NoleError::Font(
+                format!("font directory not found (searched at {})", dir.display()).into(),
+            ));
+        }
+        *self.font_dirs.write()? = dirs;
+        Ok(())
+    }
+
+    /// Compile a file from disk in a fresh world, independent of the editor.
+    pub fn compile_file(&self, root: PathBuf, input: PathBuf) -> NoleResult<Document> {
+        let core = self.core(&root)?;
+        let world = NoleWorld::new(root, input, core)?;
+        world.compile().map(|(document, _)| document)
+    }
+
+    /// Resolve the file that is compiled when `path` of the workspace is edited.
+    ///
+    /// That is the pinned entry of the workspace, or the workspace's `main.typ`
+    /// if its last compilation used the file. Otherwise the edited file itself,
+    /// as most notes stand on their own.
+    pub fn entry(&self, workspace: &Path, path: &Path) -> PathBuf {
+        if let Some(entry) = self.entries.read().ok().and_then(|e| e.get(workspace).cloned()) {
+            return entry;
+        }
+        let main = workspace.join(MAIN_FILE);
+        if main.is_file() {
+            self.scan(workspace, &main);
+            if self.uses(&main, path) {
+                return main;
+            }
+        }
+        path.to_path_buf()
+    }
+
+    /// Compile `main` once to learn the files it uses, if it was not compiled
+    /// yet, so a chapter opened first is previewed as part of the document.
+    fn scan(&self, workspace: &Path, main: &Path) {
+        let known = main.canonicalize().is_ok_and(|main| {
+            self.sources.read().is_ok_and(|sources| sources.contains_key(&main))
+        });
+        if known {
+            return;
+        }
+        let Ok(core) = self.core(workspace) else {
+            return;
+        };
+        if let Ok(world) = NoleWorld::new(workspace.to_path_buf(), main.to_path_buf(), core) {
+            // The files are recorded even if the compilation fails.
+            let _ = world.compile();
+            let _ = self.record_sources(&world);
+        }
+    }
+
+    /// Whether the last compilation of `input` used the source file at `path`.
+    pub fn uses(&self, input: &Path, path: &Path) -> bool {
+        let (Ok(input), Ok(path)) = (input.canonicalize(), path.canonicalize()) else {
+            return false;
+        };
+        input == path
+            || self
+                .sources
+                .read()
+                .is_ok_and(|sources| sources.get(&input).is_some_and(|s| s.contains(&path)))
+    }
+
+    /// Remember the source files the last compilation of a world used.
+    pub fn record_sources(&self, world: &NoleWorld) -> NoleResult<()> {
+        let sources: HashSet<PathBuf> = world
+            .accessed()
+            .into_iter()
+            .filter(|file| file.source && file.id.package().is_none())
+            .filter_map(|file| file.path)
+            .collect();
+        self.sources.write()?.insert(world.input().clone(), sources);
+        Ok(())
+    }
+
+    /// Get the world of the last compilation.
+    pub fn snapshot(&self) -> NoleResult<Arc<WorldSnapshot>> {
+        self.snapshot.read()?.clone().ok_or(NoleError::WorldNotInitialized)
+    }
+
+    pub fn reset(&self) -> NoleResult<()> {
+        // A compilation that panicked poisons the world, it is dropped here.
+        *self.world_cache.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        self.world_cache.clear_poison();
+        *self.snapshot.write()? = None;
+        *self.document_cache.write()? = None;
         Ok(())
     }
 }
@@ -38,17 +262,41 @@ pub struct TypstCore {
     pub fontbook: Prehashed<FontBook>,
     /// Locations of and storage for lazily loaded fonts.
     pub fonts: Vec<FontSlot>,
+    /// Keeps the recently used fonts loaded.
+    pub font_cache: FontCache,
+    /// The font directories searched before the system fonts, by priority.
+    pub font_paths: Vec<PathBuf>,
+    /// Downloaded and local packages.
+    pub packages: Arc<PackageStorage>,
 }
 
 impl TypstCore {
-    pub fn new() -> Self {
+    pub fn new(font_paths: Vec<PathBuf>, packages: Arc<PackageStorage>) -> Self {
         let mut searcher = FontSearcher::new();
-        searcher.search(&[]);
+        searcher.search(&font_paths);
 
         Self {
             library: Prehashed::new(Library::build()),
             fontbook: Prehashed::new(searcher.book),
             fonts: searcher.fonts,
+            font_cache: FontCache::new(FONT_CAPACITY),
+            font_paths,
+            packages,
         }
     }
+
+    /// Get the font at an index of the font book.
+    pub fn font(&self, index: usize) -> Option<Font> {
+        self.font_cache.get(&self.fonts, index)
+    }
+
+    /// Statistics of the loaded fonts.
+    pub fn font_stats(&self) -> FontStats {
+        self.font_cache.stats(&self.fonts)
+    }
+
+    /// The discovered font families with their variants.
+    pub fn font_families(&self) -> Vec<FontFamilyInfo> {
+        font_families(&self.fontbook, &self.fonts)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,223 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Timelike};
+use serde::{Deserialize, Serialize};
+use typst::foundations::Datetime;
+use typst::model::Document;
+use typst::visualize::Color;
+
+use crate::error::{NoleError, NoleResult};
+
+/// The pixels per inch images are exported with by default.
+pub const DEFAULT_PPI: f32 = 144.0;
+
+/// The most pixels per inch images can be exported with, an A4 page is about
+/// 20000 by 28000 pixels at it.
+pub const MAX_PPI: f32 = 2400.0;
+
+/// The formats a document can be exported to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Pdf,
+    Png,
+    Svg,
+}
+
+impl ExportFormat {
+    /// The file extension of the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Png => "png",
+            Self::Svg => "svg",
+        }
+    }
+
+    /// The default file name template of the format.
+    ///
+    /// A pdf holds the whole document, images hold one page each.
+    pub fn template(self) -> String {
+        match self {
+            Self::Pdf => format!("{{stem}}.{}", self.extension()),
+            _ => format!("{{stem}}-{{page}}.{}", self.extension()),
+        }
+    }
+}
+
+/// Write the document as pdf.
+pub fn export_pdf(document: &Document, ident: &str, path: &Path) -> NoleResult<()> {
+    let pdf = typst_pdf::pdf(document, Some(ident), now());
+    Ok(fs::write(path, pdf)?)
+}
+
+/// Write the selected pages as one image each into `dir`.
+///
+/// `pages` are zero-based indices, `template` names the files with the
+/// placeholders `{stem}`, `{page}` and `{total}`.
+///
+/// Returns the paths of the written files.
+pub fn export_images(
+    document: &Document,
+    format: ExportFormat,
+    pages: &[usize],
+    ppi: f32,
+    dir: &Path,
+    template: &str,
+    stem: &str,
+) -> NoleResult<Vec<PathBuf>> {
+    check_ppi(ppi)?;
+    // Every page would overwrite the file of the one before.
+    if pages.len() > 1 && !template.contains("{page}") {
+        return Err(format!("the file name `{template}` needs `{{page}}` for several pages").into());
+    }
+    fs::create_dir_all(dir)?;
+    let n_pages = document.pages.len();
+    let mut paths = Vec::with_capacity(pages.len());
+    for &page in pages {
+        let frame = document
+            .pages
+            .get(page)
+            .ok_or(NoleError::PageOutOfRange { page, n_pages })?;
+        let path = dir.join(output_name(template, stem, page + 1, n_pages));
+        match format {
+            ExportFormat::Png => {
+                let pixmap = typst_render::render(frame, ppi / 72.0, Color::WHITE);
+                let png = pixmap
+                    .encode_png()
+                    .map_err(|err| NoleError::Io(err.to_string().into()))?;
+                fs::write(&path, png)?;
+            }
+            ExportFormat::Svg => fs::write(&path, typst_svg::svg(frame))?,
+            ExportFormat::Pdf => {
+                return Err("pdf can not be exported per page".into());
+            }
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Check that images can be rendered with the pixels per inch.
+pub fn check_ppi(ppi: f32) -> NoleResult<()> {
+    if ppi.is_finite() && ppi > 0.0 && ppi <= MAX_PPI {
+        Ok(())
+    } else {
+        Err(format!("pixels per inch must be above 0 and at most {MAX_PPI}, got {ppi}").into())
+    }
+}
+
+/// Parse one-based page ranges like `1-3,7` or `5-` into zero-based indices.
+///
+/// An empty selection selects all pages.
+pub fn parse_pages(ranges: &str, n_pages: usize) -> NoleResult<Vec<usize>> {
+    if ranges.trim().is_empty() {
+        return Ok((0..n_pages).collect());
+    }
+
+    let number = |s: &str, default: usize| -> NoleResult<usize> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(default);
+        }
+        match s.parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!("invalid page number `{s}`").into()),
+            Ok(n) => Ok(n),
+        }
+    };
+
+    let mut pages = vec![];
+    for range in ranges.split(',') {
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (number(start, 1)?, number(end, n_pages)?),
+            None if range.trim().is_empty() => {
+                return Err(format!("empty page range in `{ranges}`").into());
+            }
+            None => {
+                let n = number(range, 0)?;
+                (n, n)
+            }
+        };
+        if start > end {
+            return Err(format!("invalid page range `{}`", range.trim()).into());
+        }
+        if end > n_pages {
+            return Err(NoleError::PageOutOfRange { page: end - 1, n_pages });
+        }
+        for page in start - 1..end {
+            if !pages.contains(&page) {
+                pages.push(page);
+            }
+        }
+    }
+    Ok(pages)
+}
+
+/// Fill a file name template for a one-based page number.
+///
+/// The page number is zero-padded to the width of the page count, so the files
+/// sort in page order.
+pub fn output_name(template: &str, stem: &str, page: usize, n_pages: usize) -> String {
+    let width = n_pages.to_string().len();
+    template
+        .replace("{stem}", stem)
+        .replace("{page}", &format!("{page:0width$}"))
+        .replace("{total}", &n_pages.to_string())
+}
+
+/// Get the current date and time in UTC.
+fn now() -> Option<Datetime> {
+    let now = chrono::Local::now().naive_utc();
+    Datetime::from_ymd_hms(
+        now.year(),
+        now.month().try_into().ok()?,
+        now.day().try_into().ok()?,
+        now.hour().try_into().ok()?,
+        now.minute().try_into().ok()?,
+        now.second().try_into().ok()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pages() {
+        assert_eq!(parse_pages("1-3,7", 10).unwrap(), vec![0, 1, 2, 6]);
+        assert_eq!(parse_pages("5-", 7).unwrap(), vec![4, 5, 6]);
+        assert_eq!(parse_pages("-", 3).unwrap(), vec![0, 1, 2]);
+        assert_eq!(parse_pages("", 2).unwrap(), vec![0, 1]);
+        assert_eq!(parse_pages("2,1-3,2", 5).unwrap(), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_parse_pages_invalid() {
+        assert!(parse_pages("0", 3).is_err());
+        assert!(parse_pages("3-1", 3).is_err());
+        assert!(parse_pages("1,,2", 3).is_err());
+        assert!(matches!(
+            parse_pages("2-4", 3),
+            Err(NoleError::PageOutOfRange { page: 3, n_pages: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_check_ppi() {
+        assert!(check_ppi(DEFAULT_PPI).is_ok());
+        assert!(check_ppi(MAX_PPI).is_ok());
+        assert!(check_ppi(0.0).is_err());
+        assert!(check_ppi(-72.0).is_err());
+        assert!(check_ppi(f32::NAN).is_err());
+        assert!(check_ppi(f32::INFINITY).is_err());
+        assert!(check_ppi(MAX_PPI + 1.0).is_err());
+    }
+
+    #[test]
+    fn test_output_name() {
+        assert_eq!(output_name("{stem}-{page}.png", "notes", 3, 12), "notes-03.png");
+        assert_eq!(output_name("{stem}-{page}.png", "notes", 3, 9), "notes-3.png");
+        assert_eq!(output_name("{page}of{total}.svg", "notes", 7, 100), "007of100.svg");
+    }
+}
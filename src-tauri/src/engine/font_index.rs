@@ -0,0 +1,248 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use typst::text::FontInfo;
+
+/// The version of the index format. Bump it when the `FontInfo` of typst
+/// changes, so old indexes are discarded.
+const FONT_INDEX_VERSION: u32 = 1;
+
+/// The metadata of discovered fonts, stored on disk so fonts don't need to be
+/// parsed again on every start.
+///
+/// A file is parsed again when its modification time or size changed. Searches
+/// of different font directories share the index, each merges its files into
+/// the one on disk.
+#[derive(Serialize, Deserialize, Default)]
+pub struct FontIndex {
+    version: u32,
+    files: HashMap<PathBuf, IndexedFile>,
+    /// The files seen by this search, they replace those on disk when saving.
+    #[serde(skip)]
+    seen: HashSet<PathBuf>,
+    /// Whether the index differs from the one on disk.
+    #[serde(skip)]
+    changed: bool,
+}
+
+/// The fonts of one file, usually one, more for collections.
+#[derive(Serialize, Deserialize)]
+struct IndexedFile {
+    modified: Option<SystemTime>,
+    size: u64,
+    /// The metadata by index in the collection, none if the font is unusable.
+    faces: Vec<(u32, Option<FontInfo>)>,
+}
+
+impl FontIndex {
+    /// The file the index is stored in.
+    pub fn path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("nole/font-index.json"))
+    }
+
+    /// Load the index from disk, or start an empty one.
+    pub fn load() -> Self {
+        Self::path().map(|path| Self::load_from(&path)).unwrap_or_else(Self::new)
+    }
+
+    /// An empty index.
+    fn new() -> Self {
+        Self { version: FONT_INDEX_VERSION, ..Self::default() }
+    }
+
+    /// Load the index from a file, or start an empty one.
+    fn load_from(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Self>(&data).ok())
+            .filter(|index| index.version == FONT_INDEX_VERSION)
+            .unwrap_or_else(Self::new)
+    }
+
+    /// Get the metadata of a font, computing it if the file is new or changed.
+    pub fn info(
+        &mut self,
+        path: &Path,
+        index: u32,
+        compute: impl FnOnce() -> Option<FontInfo>,
+    ) -> Option<FontInfo> {
+        if self.seen.insert(path.to_path_buf()) {
+            let Ok(metadata) = fs::metadata(path) else {
+                self.seen.remove(path);
+                return compute();
+            };
+            let modified = metadata.modified().ok();
+            let fresh = self
+                .files
+                .get(path)
+                .is_some_and(|file| file.modified == modified && file.size == metadata.len());
+            if !fresh {
+                let file = IndexedFile { modified, size: metadata.len(), faces: vec![] };
+                self.files.insert(path.to_path_buf(), file);
+                self.changed = true;
+            }
+        }
+
+        let Some(file) = self.files.get_mut(path) else {
+            return compute();
+        };
+        if let Some((_, info)) = file.faces.iter().find(|(i, _)| *i == index) {
+            return info.clone();
+        }
+        let info = compute();
+        file.faces.push((index, info.clone()));
+        self.changed = true;
+        info
+    }
+
+    /// Write the index if it changed.
+    pub fn save(self) {
+        if !self.changed {
+            return;
+        }
+        let Some(path) = Self::path() else {
+            return;
+        };
+        if let Err(err) = self.save_to(&path) {
+            println!("Failed to save the font index: {err}");
+        }
+    }
+
+    /// Merge the index into the one in a file.
+    ///
+    /// The files of this search replace those in the file, other searches may
+    /// have saved the fonts of other directories meanwhile. Files that no
+    /// longer exist are dropped.
+    fn save_to(mut self, path: &Path) -> io::Result<()> {
+        let mut merged = Self::load_from(path);
+        merged.files.retain(|path, _| !self.seen.contains(path));
+        merged.files.extend(self.files.drain());
+        merged.files.retain(|path, _| fs::metadata(path).is_ok());
+
+        // Write next to the index and rename, so a crash can't leave half of
+        // it. Every save uses its own file, searches may save concurrently.
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let partial = path.with_extension(format!("json.{}-{n}.partial", std::process::id()));
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| serde_json::to_vec(&merged).map_err(Into::into))
+            .and_then(|data| fs::write(&partial, data))
+            .and_then(|_| fs::rename(&partial, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+
+    /// A fresh directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nole-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Look up a font and return whether it was computed.
+    fn computed(index: &mut FontIndex, path: &Path, face: u32) -> bool {
+        let mut computed = false;
+        index.info(path, face, || {
+            computed = true;
+            None
+        });
+        computed
+    }
+
+    #[test]
+    fn test_info_cache_hit() {
+        let dir = temp_dir("index-hit");
+        let font = dir.join("font.ttf");
+        fs::write(&font, b"font").unwrap();
+        let mut index = FontIndex::new();
+        assert!(computed(&mut index, &font, 0));
+        assert!(!computed(&mut index, &font, 0));
+
+        // Also across searches.
+        let file = dir.join("font-index.json");
+        index.save_to(&file).unwrap();
+        let mut index = FontIndex::load_from(&file);
+        assert!(!computed(&mut index, &font, 0));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_info_changed_file() {
+        let dir = temp_dir("index-changed");
+        let font = dir.join("font.ttf");
+        let file = dir.join("font-index.json");
+        fs::write(&font, b"font").unwrap();
+        let mut index = FontIndex::new();
+        assert!(computed(&mut index, &font, 0));
+        index.save_to(&file).unwrap();
+
+        // Another size.
+        fs::write(&font, b"another font").unwrap();
+        let mut index = FontIndex::load_from(&file);
+        assert!(computed(&mut index, &font, 0));
+        index.save_to(&file).unwrap();
+
+        // Another modification time.
+        filetime::set_file_mtime(&font, FileTime::from_unix_time(0, 0)).unwrap();
+        let mut index = FontIndex::load_from(&file);
+        assert!(computed(&mut index, &font, 0));
+        assert!(!computed(&mut index, &font, 0));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_info_collection() {
+        let dir = temp_dir("index-collection");
+        let font = dir.join("fonts.ttc");
+        fs::write(&font, b"fonts").unwrap();
+        let mut index = FontIndex::new();
+        assert!(computed(&mut index, &font, 0));
+        assert!(computed(&mut index, &font, 1));
+        assert!(!computed(&mut index, &font, 0));
+        assert!(!computed(&mut index, &font, 1));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_save_merges() {
+        let dir = temp_dir("index-merge");
+        let (a, b) = (dir.join("a.ttf"), dir.join("b.ttf"));
+        fs::write(&a, b"a").unwrap();
+        fs::write(&b, b"b").unwrap();
+        let file = dir.join("font-index.json");
+
+        let mut index = FontIndex::new();
+        computed(&mut index, &a, 0);
+        index.save_to(&file).unwrap();
+        let mut index = FontIndex::new();
+        computed(&mut index, &b, 0);
+        index.save_to(&file).unwrap();
+
+        // Neither search dropped the font of the other.
+        let mut index = FontIndex::load_from(&file);
+        assert!(!computed(&mut index, &a, 0));
+        assert!(!computed(&mut index, &b, 0));
+
+        // Deleted files are pruned.
+        fs::remove_file(&a).unwrap();
+        index.save_to(&file).unwrap();
+        assert!(!FontIndex::load_from(&file).files.contains_key(&a));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
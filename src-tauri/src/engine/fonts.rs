@@ -1,10 +1,20 @@
-use std::sync::OnceLock;
-use std::fs;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
 
 use fontdb::{Database, Source};
-use typst::text::{Font, FontBook, FontInfo};
+use memmap2::Mmap;
+use serde::Serialize;
+use typst::foundations::Bytes;
+use typst::text::{Font, FontBook, FontFlags, FontInfo, FontStyle};
 
+use super::FontIndex;
+
+/// How many fonts stay loaded before the least recently used one is dropped.
+pub const FONT_CAPACITY: usize = 64;
 
 /// Searches for fonts.
 pub struct FontSearcher {
@@ -21,20 +31,287 @@ pub struct FontSlot {
     /// The index of the font in its collection. Zero if the path does not point
     /// to a collection.
     index: u32,
-    /// The lazily loaded font.
-    font: OnceLock<Option<Font>>,
-    // font: OnceCell<Option<Font>>,
+    /// The lazily loaded font, dropped again when it is rarely used.
+    font: Mutex<Option<Font>>,
+    /// Whether the font is embedded in the binary and never dropped.
+    embedded: bool,
+    /// When the font was last used, in ticks of the [`FontCache`].
+    last_used: AtomicU64,
+}
+
+/// Keeps the most recently used fonts loaded.
+///
+/// Dropping a font frees what typst parsed from it. Its file stays mapped, see
+/// [`mapped_files`], so the system only keeps the parts of it in memory that
+/// were read and can reclaim them under memory pressure.
+pub struct FontCache {
+    /// How many fonts stay loaded.
+    capacity: usize,
+    /// Counts the uses of fonts, to find the least recently used one.
+    tick: AtomicU64,
+    /// How many fonts loaded from files are currently loaded.
+    loaded: AtomicUsize,
+    hits: AtomicU64,
+    loads: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// Statistics of the loaded fonts, for diagnostics.
+#[derive(Serialize, Clone, Debug)]
+pub struct FontStats {
+    /// The number of discovered fonts.
+    pub total: usize,
+    /// The number of fonts currently loaded from files.
+    pub loaded: usize,
+    /// The number of fonts embedded in the binary, which are always loaded.
+    pub embedded: usize,
+    /// How many fonts stay loaded from files.
+    pub capacity: usize,
+    /// The number of memory-mapped font files. They stay mapped until nole
+    /// exits, at most once per path.
+    pub mapped_files: usize,
+    /// The size of the memory-mapped font files. This is address space, only
+    /// the parts that were read are in memory.
+    pub mapped_bytes: u64,
+    /// How often a loaded font was used.
+    pub hits: u64,
+    /// How often a font was loaded from its file.
+    pub loads: u64,
+    /// How often a font was dropped to make room for another.
+    pub evictions: u64,
+}
+
+/// The size and modification time of a font file, to notice changes.
+type FileStamp = (Option<SystemTime>, u64);
+
+/// The memory-mapped font files, by path.
+///
+/// `Bytes` can only borrow static data, so a mapping is never released. To
+/// keep that bounded, every path is mapped at most once: the buffer is reused
+/// when a dropped font is loaded again, which also avoids hashing the file
+/// again. A file that changed since it was mapped is read into memory instead,
+/// and that copy is freed with the last font using it.
+fn mapped_files() -> &'static Mutex<HashMap<PathBuf, (FileStamp, Bytes)>> {
+    static MAPPED: OnceLock<Mutex<HashMap<PathBuf, (FileStamp, Bytes)>>> = OnceLock::new();
+    MAPPED.get_or_init(Mutex::default)
+}
+
+/// Get the data of a font file, mapping it into memory on first use.
+fn load(path: &Path) -> Option<Bytes> {
+    let file = File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    let stamp = (metadata.modified().ok(), metadata.len());
+    let mut mapped = mapped_files().lock().ok()?;
+    match mapped.get(path) {
+        Some((mapped_stamp, data)) if *mapped_stamp == stamp => Some(data.clone()),
+        Some(_) => std::fs::read(path).ok().map(Bytes::from),
+        None => {
+            // Safety: Font files are not expected to be truncated while nole
+            // runs. A changed file is read again instead of using the mapping.
+            let mmap = unsafe { Mmap::map(&file) }.ok()?;
+            let mmap: &'static [u8] = Box::leak(Box::new(mmap));
+            let data = Bytes::from_static(mmap);
+            mapped.insert(path.to_path_buf(), (stamp, data.clone()));
+            Some(data)
+        }
+    }
 }
 
 impl FontSlot {
-    /// Get the font for this slot.
-    pub fn get(&self) -> Option<Font> {
-        self.font
-            .get_or_init(|| {
-                let data = fs::read(&self.path).ok()?.into();
-                Font::new(data, self.index)
-            })
-            .clone()
+    /// The file the font is loaded from, none for embedded fonts.
+    pub fn path(&self) -> Option<&Path> {
+        (!self.embedded).then_some(self.path.as_path())
+    }
+
+    /// Get the font for this slot, if it is loaded.
+    fn loaded(&self) -> Option<Font> {
+        self.font.lock().ok()?.clone()
+    }
+
+    /// Drop the font, unless it is embedded.
+    fn unload(&self) -> bool {
+        if self.embedded {
+            return false;
+        }
+        match self.font.lock() {
+            Ok(mut font) => font.take().is_some(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl FontCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: AtomicU64::new(0),
+            loaded: AtomicUsize::new(0),
+            hits: AtomicU64::new(0),
+            loads: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the font of a slot, loading it and dropping the least recently used
+    /// font if too many are loaded.
+    pub fn get(&self, fonts: &[FontSlot], index: usize) -> Option<Font> {
+        let slot = fonts.get(index)?;
+        slot.last_used
+            .store(self.tick.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        if let Some(font) = slot.loaded() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(font);
+        }
+
+        let font = Font::new(load(&slot.path)?, slot.index)?;
+        {
+            let mut loaded = slot.font.lock().ok()?;
+            if let Some(font) = loaded.as_ref() {
+                // Another thread was faster.
+                return Some(font.clone());
+            }
+            *loaded = Some(font.clone());
+        }
+        self.loads.fetch_add(1, Ordering::Relaxed);
+        if self.loaded.fetch_add(1, Ordering::Relaxed) + 1 > self.capacity {
+            self.evict(fonts, index);
+        }
+        Some(font)
+    }
+
+    /// Drop the least recently used font loaded from a file, except `keep`.
+    fn evict(&self, fonts: &[FontSlot], keep: usize) {
+        let lru = fonts
+            .iter()
+            .enumerate()
+            .filter(|&(i, slot)| i != keep && !slot.embedded && slot.loaded().is_some())
+            .min_by_key(|(_, slot)| slot.last_used.load(Ordering::Relaxed));
+        if let Some((_, slot)) = lru {
+            if slot.unload() {
+                self.loaded.fetch_sub(1, Ordering::Relaxed);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Statistics of the loaded fonts.
+    pub fn stats(&self, fonts: &[FontSlot]) -> FontStats {
+        let (mapped_files, mapped_bytes) = match mapped_files().lock() {
+            Ok(mapped) => {
+                let bytes = mapped.values().map(|(_, data)| data.len() as u64).sum();
+                (mapped.len(), bytes)
+            }
+            Err(_) => (0, 0),
+        };
+        FontStats {
+            total: fonts.len(),
+            loaded: self.loaded.load(Ordering::Relaxed),
+            embedded: fonts.iter().filter(|slot| slot.embedded).count(),
+            capacity: self.capacity,
+            mapped_files,
+            mapped_bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            loads: self.loads.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A font family and its variants, for the font picker and completion.
+#[derive(Serialize, Clone, Debug)]
+pub struct FontFamilyInfo {
+    /// The name to use in `text(font: ..)`.
+    pub name: String,
+    pub variants: Vec<FontVariantInfo>,
+}
+
+/// One font of a family.
+#[derive(Serialize, Clone, Debug)]
+pub struct FontVariantInfo {
+    /// `normal`, `italic` or `oblique`.
+    pub style: &'static str,
+    /// From 100 (thin) to 900 (black), 400 is regular.
+    pub weight: u16,
+    /// The width relative to the normal width, from 0.5 to 2.0.
+    pub stretch: f64,
+    pub monospace: bool,
+    pub serif: bool,
+    pub math: bool,
+    pub variable: bool,
+    /// The scripts the font has glyphs for, e.g. `latin` or `cjk`.
+    pub coverage: Vec<&'static str>,
+    pub source: FontSource,
+}
+
+/// Where a font was loaded from.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FontSource {
+    /// A font file, `index` is the font's index in a collection.
+    File { path: PathBuf, index: u32 },
+    /// A font bundled with nole.
+    Embedded,
+}
+
+/// Scripts reported in the coverage of fonts, each with a character that a
+/// font covering the script must have.
+const SCRIPTS: &[(&str, char)] = &[
+    ("latin", 'a'),
+    ("greek", 'α'),
+    ("cyrillic", 'ж'),
+    ("arabic", 'ع'),
+    ("hebrew", 'א'),
+    ("devanagari", 'क'),
+    ("thai", 'ก'),
+    ("cjk", '中'),
+    ("kana", 'あ'),
+    ("hangul", '한'),
+    ("math", '∑'),
+];
+
+/// List the families of a font book with their variants, sorted by name.
+///
+/// `fonts` are the slots of the book's fonts, in the same order.
+pub fn font_families(book: &FontBook, fonts: &[FontSlot]) -> Vec<FontFamilyInfo> {
+    let mut families: BTreeMap<String, FontFamilyInfo> = BTreeMap::new();
+    for (index, slot) in fonts.iter().enumerate() {
+        let Some(info) = book.info(index) else {
+            continue;
+        };
+        families
+            .entry(info.family.to_lowercase())
+            .or_insert_with(|| FontFamilyInfo { name: info.family.clone(), variants: vec![] })
+            .variants
+            .push(FontVariantInfo::new(info, slot));
+    }
+    families.into_values().collect()
+}
+
+impl FontVariantInfo {
+    fn new(info: &FontInfo, slot: &FontSlot) -> Self {
+        let variant = info.variant;
+        Self {
+            style: match variant.style {
+                FontStyle::Normal => "normal",
+                FontStyle::Italic => "italic",
+                FontStyle::Oblique => "oblique",
+            },
+            weight: variant.weight.to_number(),
+            stretch: variant.stretch.to_ratio().get(),
+            monospace: info.flags.contains(FontFlags::MONOSPACE),
+            serif: info.flags.contains(FontFlags::SERIF),
+            math: info.flags.contains(FontFlags::MATH),
+            variable: info.flags.contains(FontFlags::VARIABLE),
+            coverage: SCRIPTS
+                .iter()
+                .filter(|(_, c)| info.coverage.contains(*c as u32))
+                .map(|(script, _)| *script)
+                .collect(),
+            source: match slot.path() {
+                Some(path) => FontSource::File { path: path.to_path_buf(), index: slot.index },
+                None => FontSource::Embedded,
+            },
+        }
     }
 }
 
@@ -56,6 +333,9 @@ impl FontSearcher {
         // System fonts have second priority.
         db.load_system_fonts();
 
+        // Reuse the metadata of fonts parsed by earlier searches.
+        let mut index = FontIndex::load();
+
         for face in db.faces() {
             let path = match &face.source {
                 Source::File(path) | Source::SharedFile(path, _) => path,
@@ -64,20 +344,23 @@ impl FontSearcher {
                 Source::Binary(_) => continue,
             };
 
-            let info = db
-                .with_face_data(face.id, FontInfo::new)
-                .expect("database must contain this font");
+            let info = index.info(path, face.index, || {
+                db.with_face_data(face.id, FontInfo::new)
+                    .expect("database must contain this font")
+            });
 
             if let Some(info) = info {
                 self.book.push(info);
                 self.fonts.push(FontSlot {
                     path: path.clone(),
                     index: face.index,
-                    font: OnceLock::new(),
-                    // font: OnceCell::new(),
+                    font: Mutex::new(None),
+                    embedded: false,
+                    last_used: AtomicU64::new(0),
                 });
             }
         }
+        index.save();
 
         // Embedded fonts have lowest priority.
         #[cfg(feature = "embed-fonts")]
@@ -88,14 +371,15 @@ impl FontSearcher {
     #[cfg(feature = "embed-fonts")]
     fn add_embedded(&mut self) {
         let mut process = |bytes: &'static [u8]| {
-            let buffer = typst::foundations::Bytes::from_static(bytes);
+            let buffer = Bytes::from_static(bytes);
             for (i, font) in Font::iter(buffer).enumerate() {
                 self.book.push(font.info().clone());
                 self.fonts.push(FontSlot {
                     path: PathBuf::new(),
                     index: i as u32,
-                    font: OnceLock::from(Some(font)),
-                    // font: OnceCell::from(Some(font)),
+                    font: Mutex::new(Some(font)),
+                    embedded: true,
+                    last_used: AtomicU64::new(0),
                 });
             }
         };
@@ -121,9 +405,14 @@ impl FontSearcher {
         add!("DejaVuSansMono-Bold.ttf");
         add!("DejaVuSansMono-Oblique.ttf");
         add!("DejaVuSansMono-BoldOblique.ttf");
-        // add!("SimSun.ttf");
-        // add!("SimHei.ttf");
-        // add!("SimKai.ttf");
-        // add!("PingFang.ttc");
+
+        // Embed open-licensed CJK fonts, for systems without any.
+        #[cfg(feature = "embed-cjk-fonts")]
+        {
+            add!("cjk/NotoSerifSC-Regular.otf");
+            add!("cjk/NotoSerifSC-Bold.otf");
+            add!("cjk/NotoSansSC-Regular.otf");
+            add!("cjk/NotoSansSC-Bold.otf");
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use typst::layout::{Frame, FrameItem};
+use typst::model::Document;
+use typst::syntax::Span;
+use typst::text::TextItem;
+use typst::World;
+
+use super::TypstLocation;
+
+/// Why text was not rendered with the intended font.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FontIssueKind {
+    /// No font has the glyphs, they render as boxes.
+    Missing,
+    /// The glyphs came from another font than the rest of the text.
+    Fallback,
+}
+
+/// Text that rendered as boxes or with a fallback font.
+#[derive(Serialize, Clone, Debug)]
+pub struct FontIssue {
+    #[serde(flatten)]
+    pub location: TypstLocation,
+    pub kind: FontIssueKind,
+    /// The affected characters.
+    pub text: String,
+    /// The family the text was rendered with.
+    pub font: String,
+    /// The family the rest of the text was rendered with, for fallbacks.
+    pub requested: Option<String>,
+    /// The one-based page the text is on.
+    pub page: usize,
+}
+
+/// A glyph of the document with the text element it was laid out from.
+struct LaidGlyph<'a> {
+    page: usize,
+    span: Span,
+    family: &'a str,
+    text: &'a str,
+    missing: bool,
+}
+
+/// Find text whose glyphs are missing or fell back to another font.
+///
+/// The requested font is not recorded in the document, so a fallback is
+/// detected when the glyphs of one text element come from several fonts: the
+/// font with the most glyphs is taken as the requested one. Text that falls
+/// back as a whole is not reported.
+pub fn find_font_issues(world: &dyn World, document: &Document) -> Vec<FontIssue> {
+    let mut glyphs = vec![];
+    for (i, frame) in document.pages.iter().enumerate() {
+        collect_glyphs(frame, i + 1, &mut glyphs);
+    }
+
+    let mut counts: HashMap<(Span, &str), usize> = HashMap::new();
+    for glyph in glyphs.iter().filter(|glyph| !glyph.missing) {
+        *counts.entry((glyph.span, glyph.family)).or_default() += 1;
+    }
+    let mut dominant: HashMap<Span, (&str, usize)> = HashMap::new();
+    for (&(span, family), &count) in &counts {
+        let entry = dominant.entry(span).or_insert((family, count));
+        if count > entry.1 || (count == entry.1 && family < entry.0) {
+            *entry = (family, count);
+        }
+    }
+
+    // Merge the glyphs of a text element that share an issue.
+    let mut issues: Vec<FontIssue> = vec![];
+    let mut index: HashMap<(Span, FontIssueKind, &str), usize> = HashMap::new();
+    for glyph in &glyphs {
+        let requested = dominant.get(&glyph.span).map(|&(family, _)| family);
+        let kind = if glyph.missing {
+            FontIssueKind::Missing
+        } else if requested.is_some_and(|requested| requested != glyph.family) {
+            FontIssueKind::Fallback
+        } else {
+            continue;
+        };
+        match index.get(&(glyph.span, kind, glyph.family)) {
+            Some(&i) => {
+                if !issues[i].text.contains(glyph.text) {
+                    issues[i].text.push_str(glyph.text);
+                }
+            }
+            None => {
+                index.insert((glyph.span, kind, glyph.family), issues.len());
+                issues.push(FontIssue {
+                    location: TypstLocation::new(world, glyph.span),
+                    kind,
+                    text: glyph.text.to_string(),
+                    font: glyph.family.to_string(),
+                    requested: match kind {
+                        FontIssueKind::Missing => None,
+                        FontIssueKind::Fallback => requested.map(str::to_string),
+                    },
+                    page: glyph.page,
+                });
+            }
+        }
+    }
+    issues
+}
+
+/// Collect the glyphs of a frame and its groups.
+fn collect_glyphs<'a>(frame: &'a Frame, page: usize, glyphs: &mut Vec<LaidGlyph<'a>>) {
+    for (_, item) in frame.items() {
+        match item {
+            FrameItem::Group(group) => collect_glyphs(&group.frame, page, glyphs),
+            FrameItem::Text(text) => collect_text(text, page, glyphs),
+            _ => {}
+        }
+    }
+}
+
+/// Collect the glyphs of a text run.
+fn collect_text<'a>(item: &'a TextItem, page: usize, glyphs: &mut Vec<LaidGlyph<'a>>) {
+    let family = item.font.info().family.as_str();
+    for glyph in &item.glyphs {
+        glyphs.push(LaidGlyph {
+            page,
+            span: glyph.span.0,
+            family,
+            text: item.text.get(glyph.range()).unwrap_or_default(),
+            missing: glyph.id == 0,
+        });
+    }
+}
@@ -1,7 +1,23 @@
+mod batch;
+mod diagnostic;
 mod engine;
+mod export;
+mod font_index;
 mod fonts;
+mod glyphs;
+mod package;
+mod queue;
+mod watch;
 pub mod world;
 
+pub use batch::*;
+pub use diagnostic::*;
 pub use engine::*;
+pub use export::*;
+pub use font_index::*;
 pub use fonts::*;
+pub use glyphs::*;
+pub use package::*;
+pub use queue::*;
+pub use watch::*;
 pub use world::*;
@@ -0,0 +1,480 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use serde::{Deserialize, Serialize};
+use typst::diag::{PackageError, PackageResult};
+use typst::foundations::eco_format;
+use typst::syntax::PackageSpec;
+
+/// The registry that `@preview` packages are downloaded from by default.
+pub const DEFAULT_REGISTRY: &str = "https://packages.typst.org";
+
+/// The environment variable that overrides the default registry.
+pub const REGISTRY_ENV: &str = "NOLE_PACKAGE_REGISTRY";
+
+/// The manifest file every package must contain.
+pub const MANIFEST: &str = "typst.toml";
+
+/// The directory of in-repo packages, relative to the workspace.
+pub const WORKSPACE_PACKAGES: &str = ".nole/packages";
+
+/// The size of the chunks a package archive is read in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The stage of a package download.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageState {
+    Downloading,
+    Unpacking,
+    Done,
+    Failed,
+}
+
+/// Progress of a package download, reported to the listener.
+#[derive(Serialize, Clone, Debug)]
+pub struct PackageProgress {
+    pub spec: String,
+    pub state: PackageState,
+    /// The number of bytes downloaded so far.
+    pub downloaded: usize,
+    /// The size of the archive, if the registry reports it.
+    pub total: Option<usize>,
+}
+
+/// A package that is available on disk.
+#[derive(Serialize, Clone, Debug)]
+pub struct PackageInfo {
+    pub namespace: String,
+    pub name: String,
+    pub version: String,
+    pub path: PathBuf,
+}
+
+/// The `typst.toml` of a package.
+#[derive(Deserialize, Debug)]
+pub struct PackageManifest {
+    pub package: PackageMeta,
+}
+
+/// The `[package]` section of a manifest.
+#[derive(Deserialize, Debug)]
+pub struct PackageMeta {
+    pub name: String,
+    pub version: String,
+    pub entrypoint: String,
+}
+
+impl PackageManifest {
+    /// Read the manifest of the package in `dir` and check it against `spec`
+    /// and that its entrypoint exists.
+    pub fn validate(dir: &Path, spec: &PackageSpec) -> PackageResult<Self> {
+        let path = dir.join(MANIFEST);
+        let other = |msg: String| PackageError::Other(Some(msg.into()));
+        let text = fs::read_to_string(&path)
+            .map_err(|err| other(format!("failed to read {}: {err}", path.display())))?;
+        let manifest: Self = toml::from_str(&text)
+            .map_err(|err| other(format!("invalid {}: {err}", path.display())))?;
+
+        if manifest.package.name != spec.name.as_str() {
+            return Err(other(format!(
+                "package name `{}` in {} does not match {spec}",
+                manifest.package.name,
+                path.display()
+            )));
+        }
+        if manifest.package.version != spec.version.to_string() {
+            return Err(other(format!(
+                "package version `{}` in {} does not match {spec}",
+                manifest.package.version,
+                path.display()
+            )));
+        }
+        let entrypoint = dir.join(&manifest.package.entrypoint);
+        if !entrypoint.is_file() {
+            return Err(other(format!(
+                "entrypoint `{}` of {spec} not found (searched at {})",
+                manifest.package.entrypoint,
+                entrypoint.display()
+            )));
+        }
+        Ok(manifest)
+    }
+}
+
+/// Downloads packages and keeps track of the ones on disk.
+pub struct PackageStorage {
+    /// The base url of the registry, either `http(s)://` or a `file://` mirror.
+    registry: RwLock<String>,
+    /// Receives progress of package downloads.
+    listener: RwLock<Option<Box<dyn Fn(PackageProgress) + Send + Sync>>>,
+    /// Package directories whose manifest was validated, so it is read once
+    /// instead of for every file of the package.
+    validated: RwLock<HashSet<PathBuf>>,
+    /// A lock per package, so one download of a package runs at a time.
+    installing: Mutex<HashMap<PackageSpec, Arc<Mutex<()>>>>,
+}
+
+impl PackageStorage {
+    /// Create a new storage using the registry from the environment, if set.
+    pub fn new() -> Self {
+        let registry = std::env::var(REGISTRY_ENV)
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| DEFAULT_REGISTRY.to_string());
+        Self {
+            registry: RwLock::new(registry),
+            listener: RwLock::new(None),
+            validated: RwLock::new(HashSet::new()),
+            installing: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The base url of the registry.
+    pub fn registry(&self) -> String {
+        self.registry
+            .read()
+            .map(|r| r.clone())
+            .unwrap_or_else(|_| DEFAULT_REGISTRY.to_string())
+    }
+
+    /// Change the registry that packages are downloaded from.
+    pub fn set_registry(&self, url: String) {
+        if let Ok(mut registry) = self.registry.write() {
+            *registry = url.trim_end_matches('/').to_string();
+        }
+    }
+
+    /// Set the listener that is notified about download progress.
+    pub fn on_progress(&self, f: impl Fn(PackageProgress) + Send + Sync + 'static) {
+        if let Ok(mut listener) = self.listener.write() {
+            *listener = Some(Box::new(f));
+        }
+    }
+
+    /// The directories packages are searched in, by priority.
+    ///
+    /// The packages of the workspace take precedence over the global ones.
+    pub fn dirs(workspace: Option<&Path>) -> Vec<PathBuf> {
+        let global = [dirs::data_dir(), dirs::cache_dir()]
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.join("typst/packages"));
+        workspace
+            .map(|root| root.join(WORKSPACE_PACKAGES))
+            .into_iter()
+            .chain(global)
+            .collect()
+    }
+
+    /// The directory downloaded packages are stored in.
+    pub fn cache_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("typst/packages"))
+    }
+
+    /// Make a package available in the on-disk cache.
+    ///
+    /// Looks into the packages of the `workspace` first.
+    pub fn prepare(&self, spec: &PackageSpec, workspace: &Path) -> PackageResult<PathBuf> {
+        let subdir = format!("{}/{}/{}", spec.namespace, spec.name, spec.version);
+
+        for dir in Self::dirs(Some(workspace)) {
+            let dir = dir.join(&subdir);
+            if dir.exists() {
+                if !self.is_validated(&dir) {
+                    PackageManifest::validate(&dir, spec)?;
+                    self.set_validated(&dir, true);
+                }
+                return Ok(dir);
+            }
+        }
+
+        // Download from network if it doesn't exist yet.
+        if spec.namespace == "preview" {
+            let lock = self.lock(spec);
+            let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            // Another world may have downloaded it while we waited.
+            if let Some(dir) = Self::cache_dir().map(|dir| dir.join(&subdir)) {
+                if dir.exists() && self.is_validated(&dir) {
+                    return Ok(dir);
+                }
+            }
+            return self.install_locked(spec);
+        }
+
+        Err(PackageError::NotFound(spec.clone()))
+    }
+
+    /// Download a package into the cache, replacing an existing copy.
+    pub fn install(&self, spec: &PackageSpec) -> PackageResult<PathBuf> {
+        let lock = self.lock(spec);
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.install_locked(spec)
+    }
+
+    /// The lock of the downloads of a package.
+    fn lock(&self, spec: &PackageSpec) -> Arc<Mutex<()>> {
+        let mut installing = self.installing.lock().unwrap_or_else(PoisonError::into_inner);
+        installing.entry(spec.clone()).or_default().clone()
+    }
+
+    /// Download a package into the cache, holding its lock.
+    fn install_locked(&self, spec: &PackageSpec) -> PackageResult<PathBuf> {
+        let dir = Self::cache_dir()
+            .ok_or(PackageError::Other(Some("cache directory not found".into())))?
+            .join(format!("{}/{}/{}", spec.namespace, spec.name, spec.version));
+
+        self.set_validated(&dir, false);
+        let result = self.download(spec, &dir);
+        if result.is_ok() {
+            self.set_validated(&dir, true);
+        }
+        let state = if result.is_ok() { PackageState::Done } else { PackageState::Failed };
+        self.report(spec, state, 0, None);
+        result.map(|_| dir)
+    }
+
+    /// Remove a package from disk.
+    pub fn remove(&self, spec: &PackageSpec) -> PackageResult<()> {
+        let subdir = format!("{}/{}/{}", spec.namespace, spec.name, spec.version);
+        let dir = Self::dirs(None)
+            .into_iter()
+            .map(|dir| dir.join(&subdir))
+            .find(|dir| dir.exists())
+            .ok_or_else(|| PackageError::NotFound(spec.clone()))?;
+        self.set_validated(&dir, false);
+        fs::remove_dir_all(&dir)
+            .map_err(|err| PackageError::Other(Some(eco_format!("{err}"))))
+    }
+
+    /// List all packages available on disk, including those of the workspace.
+    pub fn list(&self, workspace: Option<&Path>) -> Vec<PackageInfo> {
+        let mut packages = vec![];
+        for dir in Self::dirs(workspace) {
+            for namespace in read_dirs(&dir) {
+                for name in read_dirs(&namespace) {
+                    for version in read_dirs(&name) {
+                        packages.push(PackageInfo {
+                            namespace: file_name(&namespace),
+                            name: file_name(&name),
+                            version: file_name(&version),
+                            path: version,
+                        });
+                    }
+                }
+            }
+        }
+        packages
+    }
+
+    /// Download and unpack a package archive into `dir`.
+    fn download(&self, spec: &PackageSpec, dir: &Path) -> PackageResult<()> {
+        let url = format!(
+            "{}/{}/{}-{}.tar.gz",
+            self.registry(),
+            spec.namespace,
+            spec.name,
+            spec.version
+        );
+
+        let network = |err: String| PackageError::NetworkFailed(Some(err.into()));
+        let (mut reader, total): (Box<dyn Read>, Option<usize>) =
+            match url.strip_prefix("file://") {
+                Some(path) => {
+                    let file = fs::File::open(path).map_err(|err| match err.kind() {
+                        io::ErrorKind::NotFound => PackageError::NotFound(spec.clone()),
+                        _ => network(err.to_string()),
+                    })?;
+                    let total = file.metadata().ok().map(|m| m.len() as usize);
+                    (Box::new(file), total)
+                }
+                None => {
+                    let response = ureq::get(&url).call().map_err(|err| match err {
+                        ureq::Error::Status(404, _) => PackageError::NotFound(spec.clone()),
+                        err => network(err.to_string()),
+                    })?;
+                    let total = response
+                        .header("Content-Length")
+                        .and_then(|len| len.parse().ok());
+                    (Box::new(response.into_reader()), total)
+                }
+            };
+
+        // Read the archive in chunks to report the progress.
+        let mut data = Vec::with_capacity(total.unwrap_or(0));
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let n = reader.read(&mut buf).map_err(|err| network(err.to_string()))?;
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+            self.report(spec, PackageState::Downloading, data.len(), total);
+        }
+
+        self.report(spec, PackageState::Unpacking, data.len(), total);
+
+        // Unpack next to the target first, so that a broken archive never
+        // leaves a half-written package behind.
+        let tmp = unique(dir, "partial");
+        let decompressed = flate2::read::GzDecoder::new(data.as_slice());
+        tar::Archive::new(decompressed).unpack(&tmp).map_err(|err| {
+            let _ = fs::remove_dir_all(&tmp);
+            PackageError::MalformedArchive(Some(eco_format!("{err}")))
+        })?;
+
+        if let Err(err) = PackageManifest::validate(&tmp, spec) {
+            let _ = fs::remove_dir_all(&tmp);
+            return Err(err);
+        }
+
+        // Move an existing copy aside first, so it is never missing for
+        // longer than a rename.
+        let old = unique(dir, "old");
+        let replaced = fs::rename(dir, &old).is_ok();
+        let result = fs::rename(&tmp, dir).map_err(|err| {
+            let _ = fs::remove_dir_all(&tmp);
+            PackageError::Other(Some(eco_format!("{err}")))
+        });
+        if replaced {
+            let _ = fs::remove_dir_all(&old);
+        }
+        result
+    }
+
+    /// Whether the manifest of the package in `dir` was validated.
+    fn is_validated(&self, dir: &Path) -> bool {
+        self.validated.read().is_ok_and(|validated| validated.contains(dir))
+    }
+
+    /// Remember whether the manifest of the package in `dir` is valid.
+    fn set_validated(&self, dir: &Path, valid: bool) {
+        if let Ok(mut validated) = self.validated.write() {
+            if valid {
+                validated.insert(dir.to_path_buf());
+            } else {
+                validated.remove(dir);
+            }
+        }
+    }
+
+    /// Notify the listener about the progress of a download.
+    fn report(&self, spec: &PackageSpec, state: PackageState, downloaded: usize, total: Option<usize>) {
+        if let Ok(listener) = self.listener.read() {
+            if let Some(listener) = listener.as_ref() {
+                listener(PackageProgress {
+                    spec: spec.to_string(),
+                    state,
+                    downloaded,
+                    total,
+                });
+            }
+        }
+    }
+}
+
+/// A path next to `dir` that no other download uses.
+fn unique(dir: &Path, suffix: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let name = file_name(dir);
+    dir.with_file_name(format!("{name}.{suffix}-{}-{n}", std::process::id()))
+}
+
+/// The sub directories of a directory.
+fn read_dirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+/// The last component of a path as a string.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// A fresh directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nole-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A registry mirror in `dir` with an archive of `spec` whose manifest
+    /// names `version`.
+    fn mirror(dir: &Path, spec: &PackageSpec, version: &str) -> PackageStorage {
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"{version}\"\nentrypoint = \"lib.typ\"\n",
+            spec.name
+        );
+        let archive = dir.join(format!("{}/{}-{}.tar.gz", spec.namespace, spec.name, spec.version));
+        fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        let encoder = flate2::write::GzEncoder::new(
+            fs::File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for (name, content) in [(MANIFEST, manifest.as_str()), ("lib.typ", "#let x = 1")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let storage = PackageStorage::new();
+        storage.set_registry(format!("file://{}/", dir.display()));
+        storage
+    }
+
+    #[test]
+    fn test_download_from_mirror() {
+        let dir = temp_dir("mirror");
+        let spec = PackageSpec::from_str("@preview/example:0.1.0").unwrap();
+        let storage = mirror(&dir, &spec, "0.1.0");
+        let target = dir.join("packages/example");
+        storage.download(&spec, &target).unwrap();
+        assert!(target.join("lib.typ").is_file());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_download_manifest_mismatch() {
+        let dir = temp_dir("mismatch");
+        let spec = PackageSpec::from_str("@preview/example:0.1.0").unwrap();
+        let storage = mirror(&dir, &spec, "0.2.0");
+        let target = dir.join("packages/example");
+        assert!(matches!(storage.download(&spec, &target), Err(PackageError::Other(_))));
+        // Nothing half-written is left behind.
+        let left = fs::read_dir(target.parent().unwrap()).map_or(0, |dir| dir.count());
+        assert_eq!(left, 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_download_not_found() {
+        let dir = temp_dir("not-found");
+        let spec = PackageSpec::from_str("@preview/example:0.1.0").unwrap();
+        let storage = mirror(&dir, &spec, "0.1.0");
+        let missing = PackageSpec::from_str("@preview/missing:0.1.0").unwrap();
+        let target = dir.join("packages/missing");
+        assert!(matches!(storage.download(&missing, &target), Err(PackageError::NotFound(_))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,121 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// A job of the queue, called with its generation.
+type Job = Box<dyn FnOnce(u64) + Send>;
+
+/// Runs compile jobs one after another on a background thread.
+///
+/// Only the latest job waits for the worker; a newer job drops the pending one,
+/// so a burst of edits results in a single compilation of the latest content.
+pub struct CompileQueue {
+    /// The generation of the latest submitted job.
+    generation: AtomicU64,
+    /// The job waiting for the worker and the signal to wake it up.
+    pending: Arc<(Mutex<Option<(u64, Job)>>, Condvar)>,
+}
+
+impl CompileQueue {
+    /// Create a new queue and start its worker.
+    pub fn new() -> Self {
+        let pending: Arc<(Mutex<Option<(u64, Job)>>, Condvar)> = Arc::default();
+        let worker = pending.clone();
+        thread::Builder::new()
+            .name("nole-compile".into())
+            .spawn(move || {
+                let (lock, signal) = &*worker;
+                loop {
+                    let job = {
+                        let Ok(mut pending) = lock.lock() else {
+                            return;
+                        };
+                        loop {
+                            if let Some(job) = pending.take() {
+                                break job;
+                            }
+                            pending = match signal.wait(pending) {
+                                Ok(pending) => pending,
+                                Err(_) => return,
+                            };
+                        }
+                    };
+                    let (generation, job) = job;
+                    // A panicking job must not take the only worker with it.
+                    if panic::catch_unwind(AssertUnwindSafe(|| job(generation))).is_err() {
+                        eprintln!("compile job {generation} panicked");
+                    }
+                }
+            })
+            .expect("failed to spawn compile worker");
+
+        Self { generation: AtomicU64::new(0), pending }
+    }
+
+    /// Schedule a job, dropping the one that is still waiting.
+    ///
+    /// Returns the generation of the job.
+    pub fn submit(&self, job: impl FnOnce(u64) + Send + 'static) -> u64 {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let (lock, signal) = &*self.pending;
+        if let Ok(mut pending) = lock.lock() {
+            *pending = Some((generation, Box::new(job)));
+            signal.notify_one();
+        }
+        generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_coalesce_to_latest() {
+        let queue = CompileQueue::new();
+        let (started, running) = mpsc::channel();
+        let (release, blocked) = mpsc::channel::<()>();
+        let (done, finished) = mpsc::channel();
+
+        // Keep the worker busy while more jobs are submitted.
+        let first = queue.submit(move |generation| {
+            started.send(()).unwrap();
+            blocked.recv().unwrap();
+            done.send(generation).unwrap();
+        });
+        running.recv_timeout(TIMEOUT).unwrap();
+
+        let (done_, finished_) = mpsc::channel();
+        let dropped = queue.submit({
+            let done = done_.clone();
+            move |generation| done.send(generation).unwrap()
+        });
+        let latest = queue.submit(move |generation| done_.send(generation).unwrap());
+        assert!(first < dropped && dropped < latest);
+
+        release.send(()).unwrap();
+        assert_eq!(finished.recv_timeout(TIMEOUT).unwrap(), first);
+        assert_eq!(finished_.recv_timeout(TIMEOUT).unwrap(), latest);
+        // The dropped job never runs, its sender is gone with it.
+        assert!(finished_.recv_timeout(TIMEOUT).is_err());
+    }
+
+    #[test]
+    fn test_survive_panic() {
+        let queue = CompileQueue::new();
+        let (started, running) = mpsc::channel();
+        queue.submit(move |_| {
+            started.send(()).unwrap();
+            panic!("typst panicked");
+        });
+        running.recv_timeout(TIMEOUT).unwrap();
+        let (done, finished) = mpsc::channel();
+        let generation = queue.submit(move |generation| done.send(generation).unwrap());
+        assert_eq!(finished.recv_timeout(TIMEOUT).unwrap(), generation);
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::error::{NoleError, NoleResult};
+
+/// Watches the files a compilation used and reports when they change on disk.
+///
+/// The parent directories are watched instead of the files themselves, so
+/// editors that save by replacing the file are noticed as well.
+pub struct DependencyWatcher {
+    watcher: RecommendedWatcher,
+    /// The watched files.
+    files: Arc<Mutex<HashSet<PathBuf>>>,
+    /// The directories of the watched files.
+    dirs: HashSet<PathBuf>,
+}
+
+impl DependencyWatcher {
+    /// Create a watcher that calls `on_change` with the changed files.
+    pub fn new(on_change: impl Fn(Vec<PathBuf>) + Send + 'static) -> NoleResult<Self> {
+        let files: Arc<Mutex<HashSet<PathBuf>>> = Arc::default();
+        let watched = files.clone();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if !matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) {
+                return;
+            }
+            let changed: Vec<PathBuf> = match watched.lock() {
+                Ok(files) => event.paths.into_iter().filter(|p| files.contains(p)).collect(),
+                Err(_) => return,
+            };
+            if !changed.is_empty() {
+                on_change(changed);
+            }
+        })
+        .map_err(|err| NoleError::Io(err.to_string().into()))?;
+
+        Ok(Self { watcher, files, dirs: HashSet::new() })
+    }
+
+    /// Watch exactly the given files from now on.
+    pub fn update(&mut self, paths: Vec<PathBuf>) {
+        let dirs: HashSet<PathBuf> = paths
+            .iter()
+            .filter_map(|path| path.parent().map(Path::to_path_buf))
+            .collect();
+        for dir in self.dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(&self.dirs) {
+            let _ = self.watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+        self.dirs = dirs;
+
+        if let Ok(mut files) = self.files.lock() {
+            *files = paths.into_iter().collect();
+        }
+    }
+}
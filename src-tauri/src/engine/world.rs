@@ -6,16 +6,19 @@ use std::sync::Arc;
 
 use chrono::{DateTime, Datelike, Local};
 use comemo::Prehashed;
-use typst::diag::{FileError, FileResult, PackageResult, PackageError, StrResult};
+use typst::diag::{FileError, FileResult, StrResult};
+use typst::eval::Tracer;
 use typst::foundations::{eco_format, Bytes, Datetime};
 use typst::Library;
 use typst::layout::Frame;
+use typst::model::Document;
 use typst::text::{Font, FontBook};
-use typst::syntax::{FileId, Source, VirtualPath, PackageSpec};
+use typst::syntax::{FileId, Source, VirtualPath};
 use typst::World;
 use typst::util::hash128;
 
-use super::TypstCore;
+use super::{PackageStorage, TypstCore, TypstDiagnostic};
+use crate::error::{NoleError, NoleResult};
 
 /// A world that provides access to the operating system.
 pub struct NoleWorld {
@@ -69,19 +72,52 @@ impl NoleWorld {
         )
     }
 
-    /// set a virtual source for render or autocomplete but not write file
-    /// 
-    /// file id also ask under the root path
-    pub fn virtual_source(&self, id: FileId, content: String) -> FileResult<Source> {
-        let slot = self.slot(id)?;
-        *slot.source.data.borrow_mut() = Some(Ok(Source::new(id, content)));
-        slot.source.accessed.set(true);
-        slot.source.data.clone().into_inner().unwrap()
+    /// Shadow the file on disk with an in-memory buffer.
+    ///
+    /// The overlay is used by every following compilation until it is cleared.
+    pub fn set_overlay(&self, id: FileId, content: String) -> FileResult<()> {
+        let mut slot = self.slot(id)?;
+        slot.overlay = Some(content);
+        slot.reset();
+        Ok(())
+    }
+
+    /// Remove the in-memory buffer of the file, so it is read from disk again.
+    pub fn clear_overlay(&self, id: FileId) {
+        if let Some(slot) = self.slots.borrow_mut().get_mut(&id) {
+            slot.overlay = None;
+            slot.reset();
+        }
+    }
+
+    /// Remove all in-memory buffers.
+    pub fn clear_overlays(&self) {
+        for slot in self.slots.borrow_mut().values_mut() {
+            slot.overlay = None;
+            slot.reset();
+        }
+    }
+
+    /// Resolve the file id of a system path under the project root.
+    pub fn id(&self, path: &Path) -> FileResult<FileId> {
+        resolve_id(&self.root, path)
     }
 
     /// Reset the compilation state in preparation of a new compilation.
+    ///
+    /// Overlays whose content was saved are dropped, so later changes on disk
+    /// are seen and watched again.
     pub fn reset(&mut self) {
+        let (root, packages) = (&self.root, &self.core.packages);
         for slot in self.slots.get_mut().values_mut() {
+            let saved = slot.overlay.as_ref().is_some_and(|overlay| {
+                slot.system_path(root, packages)
+                    .and_then(|path| read(&path))
+                    .is_ok_and(|data| data == overlay.as_bytes())
+            });
+            if saved {
+                slot.overlay = None;
+            }
             slot.reset();
         }
         self.now.take();
@@ -108,6 +144,93 @@ impl NoleWorld {
         &self.root
     }
 
+    /// The fonts and standard library the world was created with.
+    pub fn core(&self) -> &Arc<TypstCore> {
+        &self.core
+    }
+
+    /// Use other fonts, keeping the files and overlays.
+    pub fn set_core(&mut self, core: Arc<TypstCore>) {
+        self.core = core;
+    }
+
+    /// Copy the files of the last compilation and the overlays, so the editor
+    /// can query them while the next compilation runs.
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let mut sources = HashMap::new();
+        let mut files = HashMap::new();
+        for slot in self.slots.borrow().values() {
+            if !slot.accessed() && slot.overlay.is_none() {
+                continue;
+            }
+            let source = slot.source.get().or_else(|| {
+                slot.overlay.clone().map(|text| Source::new(slot.id, text))
+            });
+            if let Some(source) = source {
+                sources.insert(slot.id, source);
+            }
+            if let Some(file) = slot.file.get() {
+                files.insert(slot.id, file);
+            }
+        }
+        WorldSnapshot {
+            root: self.root.clone(),
+            input: self.input.clone(),
+            main: self.main,
+            core: self.core.clone(),
+            sources,
+            files,
+            accessed: self.accessed(),
+        }
+    }
+
+    /// The files accessed in the last compilation.
+    pub fn accessed(&self) -> Vec<AccessedFile> {
+        self.slots
+            .borrow()
+            .values()
+            .filter(|slot| slot.accessed())
+            .map(|slot| AccessedFile {
+                id: slot.id,
+                source: slot.source.accessed(),
+                file: slot.file.accessed(),
+                path: slot.system_path(&self.root, &self.core.packages).ok(),
+            })
+            .collect()
+    }
+
+    /// The system paths of the files the last compilation read from disk.
+    ///
+    /// Files shadowed by an overlay are left out.
+    pub fn dependencies(&self) -> Vec<PathBuf> {
+        self.slots
+            .borrow()
+            .values()
+            .filter(|slot| slot.accessed() && slot.overlay.is_none())
+            .filter_map(|slot| slot.system_path(&self.root, &self.core.packages).ok())
+            .collect()
+    }
+
+    /// Compile the main file.
+    ///
+    /// Returns the document and its warnings, or the errors if it failed.
+    pub fn compile(&self) -> NoleResult<(Document, Vec<TypstDiagnostic>)> {
+        let mut tracer = Tracer::new();
+        match typst::compile(self, &mut tracer) {
+            Ok(document) => {
+                let warnings = tracer
+                    .warnings()
+                    .iter()
+                    .map(|d| TypstDiagnostic::new(self, d))
+                    .collect();
+                Ok((document, warnings))
+            }
+            Err(errors) => Err(NoleError::CompileFailed(
+                errors.iter().map(|d| TypstDiagnostic::new(self, d)).collect(),
+            )),
+        }
+    }
+
     /// Gets access to the export cache.
     pub fn export_cache(&mut self) -> &mut ExportCache {
         &mut self.export_cache
@@ -128,31 +251,20 @@ impl World for NoleWorld {
     }
 
     fn source(&self, id: FileId) -> FileResult<Source> {
-        self.slot(id)?.source(&self.root)
+        self.slot(id)?.source(&self.root, &self.core.packages)
     }
 
     fn file(&self, id: FileId) -> FileResult<Bytes> {
-        self.slot(id)?.file(&self.root)
+        self.slot(id)?.file(&self.root, &self.core.packages)
     }
 
 
     fn font(&self, index: usize) -> Option<Font> {
-        self.core.fonts[index].get()
+        self.core.font(index)
     }
 
     fn today(&self, offset: Option<i64>) -> Option<Datetime> {
-        let now = self.now.get_or_init(chrono::Local::now);
-
-        let naive = match offset {
-            None => now.naive_local(),
-            Some(o) => now.naive_utc() + chrono::Duration::hours(o),
-        };
-
-        Datetime::from_ymd(
-            naive.year(),
-            naive.month().try_into().ok()?,
-            naive.day().try_into().ok()?,
-        )
+        today(self.now.get_or_init(chrono::Local::now), offset)
     }
 }
 
@@ -166,12 +278,163 @@ impl NoleWorld {
 }
 
 
+/// A read-only copy of a world after a compilation.
+///
+/// Holds the files the compilation used and the overlays, other files are read
+/// from disk. Queries of the editor run against it, so they don't wait for the
+/// next compilation.
+pub struct WorldSnapshot {
+    root: PathBuf,
+    input: PathBuf,
+    main: FileId,
+    core: Arc<TypstCore>,
+    sources: HashMap<FileId, Source>,
+    files: HashMap<FileId, Bytes>,
+    accessed: Vec<AccessedFile>,
+}
+
+impl WorldSnapshot {
+    /// Resolve the file id of a system path under the project root.
+    pub fn id(&self, path: &Path) -> FileResult<FileId> {
+        resolve_id(&self.root, path)
+    }
+
+    /// The canonical path to the input file.
+    pub fn input(&self) -> &PathBuf {
+        &self.input
+    }
+
+    /// The root relative to which absolute paths are resolved.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The files accessed in the compilation.
+    pub fn accessed(&self) -> &[AccessedFile] {
+        &self.accessed
+    }
+
+    /// View the snapshot with the unsaved content of a file from the editor.
+    ///
+    /// The snapshot itself is left untouched.
+    pub fn edit(&self, id: FileId, text: &str) -> EditedSnapshot<'_> {
+        let source = match self.source(id) {
+            Ok(mut source) => {
+                source.replace(text);
+                source
+            }
+            Err(_) => Source::new(id, text.into()),
+        };
+        EditedSnapshot { snapshot: self, source }
+    }
+}
+
+impl World for WorldSnapshot {
+    fn library(&self) -> &Prehashed<Library> {
+        &self.core.library
+    }
+
+    fn book(&self) -> &Prehashed<FontBook> {
+        &self.core.fontbook
+    }
+
+    fn main(&self) -> Source {
+        self.source(self.main).unwrap()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        if let Some(source) = self.sources.get(&id) {
+            return Ok(source.clone());
+        }
+        let data = read(&system_path(id, &self.root, &self.core.packages)?)?;
+        Ok(Source::new(id, decode_utf8(&data)?.into()))
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        if let Some(file) = self.files.get(&id) {
+            return Ok(file.clone());
+        }
+        Ok(read(&system_path(id, &self.root, &self.core.packages)?)?.into())
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        self.core.font(index)
+    }
+
+    fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+        today(&chrono::Local::now(), offset)
+    }
+}
+
+/// A snapshot with the unsaved content of one file.
+pub struct EditedSnapshot<'a> {
+    snapshot: &'a WorldSnapshot,
+    source: Source,
+}
+
+impl EditedSnapshot<'_> {
+    /// The edited source file.
+    pub fn edited(&self) -> &Source {
+        &self.source
+    }
+}
+
+impl World for EditedSnapshot<'_> {
+    fn library(&self) -> &Prehashed<Library> {
+        self.snapshot.library()
+    }
+
+    fn book(&self) -> &Prehashed<FontBook> {
+        self.snapshot.book()
+    }
+
+    fn main(&self) -> Source {
+        self.source(self.snapshot.main).unwrap()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        if id == self.source.id() {
+            return Ok(self.source.clone());
+        }
+        self.snapshot.source(id)
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        if id == self.source.id() {
+            return Ok(self.source.text().as_bytes().to_vec().into());
+        }
+        self.snapshot.file(id)
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        self.snapshot.font(index)
+    }
+
+    fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+        self.snapshot.today(offset)
+    }
+}
+
+/// A file accessed in a compilation.
+#[derive(Clone)]
+pub struct AccessedFile {
+    pub id: FileId,
+    /// Whether the file was parsed as source.
+    pub source: bool,
+    /// Whether the file was read as bytes, e.g. an image or data file.
+    pub file: bool,
+    /// The path of the file on the system.
+    pub path: Option<PathBuf>,
+}
+
 /// Holds the processed data for a file ID.
 ///
 /// Both fields can be populated if the file is both imported and read().
 struct FileSlot {
     /// The slot's file id.
     id: FileId,
+    /// The in-memory content that shadows the file on disk, if any.
+    overlay: Option<String>,
     /// The lazily loaded and incrementally updated source file.
     source: SlotCell<Source>,
     /// The lazily loaded raw byte buffer.
@@ -181,7 +444,7 @@ struct FileSlot {
 impl FileSlot {
     /// Create a new path slot.
     fn new(id: FileId) -> Self {
-        Self { id, file: SlotCell::new(), source: SlotCell::new() }
+        Self { id, overlay: None, file: SlotCell::new(), source: SlotCell::new() }
     }
 
     /// Whether the file was accessed in the ongoing compilation.
@@ -197,9 +460,9 @@ impl FileSlot {
     }
 
     /// Retrieve the source for this file.
-    fn source(&self, root: &Path) -> FileResult<Source> {
+    fn source(&self, root: &Path, packages: &PackageStorage) -> FileResult<Source> {
         self.source.get_or_init(
-            || self.system_path(root),
+            || self.load(root, packages),
             |data, prev| {
                 let text = decode_utf8(&data)?;
                 if let Some(mut prev) = prev {
@@ -213,28 +476,63 @@ impl FileSlot {
     }
 
     /// Retrieve the file's bytes.
-    fn file(&self, root: &Path) -> FileResult<Bytes> {
+    fn file(&self, root: &Path, packages: &PackageStorage) -> FileResult<Bytes> {
         self.file
-            .get_or_init(|| self.system_path(root), |data, _| Ok(data.into()))
+            .get_or_init(|| self.load(root, packages), |data, _| Ok(data.into()))
     }
 
-    /// The path of the slot on the system.
-    fn system_path(&self, root: &Path) -> FileResult<PathBuf> {
-        // Determine the root path relative to which the file path
-        // will be resolved.
-        let buf;
-        let mut root = root;
-        if let Some(spec) = self.id.package() {
-            buf = prepare_package(spec)?;
-            root = &buf;
+    /// Read the raw content of the file, preferring the overlay.
+    fn load(&self, root: &Path, packages: &PackageStorage) -> FileResult<Vec<u8>> {
+        match &self.overlay {
+            Some(content) => Ok(content.as_bytes().to_vec()),
+            None => self.system_path(root, packages).and_then(|path| read(&path)),
         }
+    }
 
-        // Join the path to the root. If it tries to escape, deny
-        // access. Note: It can still escape via symlinks.
-        self.id.vpath().resolve(root).ok_or(FileError::AccessDenied)
+    /// The path of the slot on the system.
+    fn system_path(&self, root: &Path, packages: &PackageStorage) -> FileResult<PathBuf> {
+        system_path(self.id, root, packages)
     }
 }
 
+/// The path of a file on the system.
+fn system_path(id: FileId, root: &Path, packages: &PackageStorage) -> FileResult<PathBuf> {
+    // Determine the root path relative to which the file path
+    // will be resolved.
+    let buf;
+    let mut root = root;
+    if let Some(spec) = id.package() {
+        buf = packages.prepare(spec, root)?;
+        root = &buf;
+    }
+
+    // Join the path to the root. If it tries to escape, deny
+    // access. Note: It can still escape via symlinks.
+    id.vpath().resolve(root).ok_or(FileError::AccessDenied)
+}
+
+/// Resolve the file id of a system path under the project root.
+fn resolve_id(root: &Path, path: &Path) -> FileResult<FileId> {
+    // The file may not exist yet when it is only held in the editor.
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let vpath = VirtualPath::within_root(&path, root).ok_or(FileError::AccessDenied)?;
+    Ok(FileId::new(None, vpath))
+}
+
+/// The current date, in the given UTC offset or local time.
+fn today(now: &DateTime<Local>, offset: Option<i64>) -> Option<Datetime> {
+    let naive = match offset {
+        None => now.naive_local(),
+        Some(o) => now.naive_utc() + chrono::Duration::hours(o),
+    };
+
+    Datetime::from_ymd(
+        naive.year(),
+        naive.month().try_into().ok()?,
+        naive.day().try_into().ok()?,
+    )
+}
+
 /// Lazily processes data for a file.
 struct SlotCell<T> {
     /// The processed data.
@@ -266,10 +564,15 @@ impl<T: Clone> SlotCell<T> {
         self.accessed.set(false);
     }
 
+    /// The loaded data, if it was loaded without errors.
+    fn get(&self) -> Option<T> {
+        self.data.borrow().as_ref()?.as_ref().ok().cloned()
+    }
+
     /// Gets the contents of the cell or initialize them.
     fn get_or_init(
         &self,
-        path: impl FnOnce() -> FileResult<PathBuf>,
+        load: impl FnOnce() -> FileResult<Vec<u8>>,
         f: impl FnOnce(Vec<u8>, Option<T>) -> FileResult<T>,
     ) -> FileResult<T> {
         let mut borrow = self.data.borrow_mut();
@@ -282,7 +585,7 @@ impl<T: Clone> SlotCell<T> {
         }
 
         // Read and hash the file.
-        let result = path().and_then(|p| read(&p));
+        let result = load();
         let fingerprint = typst::util::hash128(&result);
 
         // If the file contents didn't change, yield the old processed data.
@@ -316,35 +619,6 @@ fn decode_utf8(buf: &[u8]) -> FileResult<&str> {
     Ok(std::str::from_utf8(buf.strip_prefix(b"\xef\xbb\xbf").unwrap_or(buf))?)
 }
 
-/// Make a package available in the on-disk cache.
-pub fn prepare_package(spec: &PackageSpec) -> PackageResult<PathBuf> {
-    let subdir =
-        format!("typst/packages/{}/{}/{}", spec.namespace, spec.name, spec.version);
-
-    if let Some(data_dir) = dirs::data_dir() {
-        let dir = data_dir.join(&subdir);
-        if dir.exists() {
-            return Ok(dir);
-        }
-    }
-
-    if let Some(cache_dir) = dirs::cache_dir() {
-        let dir = cache_dir.join(&subdir);
-
-        // Download from network if it doesn't exist yet.
-        // if spec.namespace == "preview" && !dir.exists() {
-        //     download_package(spec, &dir)?;
-        // }
-
-        if dir.exists() {
-            return Ok(dir);
-        }
-    }
-
-    Err(PackageError::NotFound(spec.clone()))
-}
-
-
 /// Caches exported files so that we can avoid re-exporting them if they haven't
 /// changed.
 ///
@@ -0,0 +1,156 @@
+use std::any::Any;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::sync::PoisonError;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use typst::diag::{EcoString, FileError, PackageError};
+
+use crate::engine::TypstDiagnostic;
+
+/// The result type of nole's commands.
+pub type NoleResult<T> = Result<T, NoleError>;
+
+/// An error of nole's commands.
+///
+/// Serialized as `{ kind, message }`, where `kind` is a stable code the
+/// frontend can branch on. Failed compilations also carry their `diagnostics`.
+#[derive(Debug, Clone)]
+pub enum NoleError {
+    /// A lock was poisoned by a panicking thread.
+    LockPoisoned,
+    /// No world was created before it was used.
+    WorldNotInitialized,
+    /// Nothing was compiled before the document was used.
+    DocumentNotInitialized,
+    /// A page that the document does not have was requested.
+    PageOutOfRange { page: usize, n_pages: usize },
+    /// The compilation was dropped in favour of a newer one.
+    Superseded,
+    /// Reading or writing a file failed.
+    Io(EcoString),
+    /// A package could not be prepared.
+    Package(EcoString),
+    /// A font could not be loaded.
+    Font(EcoString),
+    /// The document has errors.
+    CompileFailed(Vec<TypstDiagnostic>),
+    /// Any other error.
+    Other(EcoString),
+}
+
+impl NoleError {
+    /// The stable code of the error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::LockPoisoned => "lock_poisoned",
+            Self::WorldNotInitialized => "world_not_initialized",
+            Self::DocumentNotInitialized => "document_not_initialized",
+            Self::PageOutOfRange { .. } => "page_out_of_range",
+            Self::Superseded => "superseded",
+            Self::Io(_) => "io",
+            Self::Package(_) => "package",
+            Self::Font(_) => "font",
+            Self::CompileFailed(_) => "compile_failed",
+            Self::Other(_) => "other",
+        }
+    }
+}
+
+impl NoleError {
+    /// The error of a compilation that panicked, with the panic message.
+    pub fn panicked(payload: Box<dyn Any + Send>) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Self::Other(format!("compilation panicked: {message}").into())
+    }
+}
+
+impl std::error::Error for NoleError {}
+
+impl Display for NoleError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::LockPoisoned => f.pad("lock poisoned"),
+            Self::WorldNotInitialized => f.pad("world not initialized"),
+            Self::DocumentNotInitialized => f.pad("document not initialized"),
+            Self::PageOutOfRange { page, n_pages } => {
+                write!(f, "page {page} out of range (document has {n_pages} pages)")
+            }
+            Self::Superseded => f.pad("compilation superseded by a newer one"),
+            Self::Io(message) => write!(f, "io error: {message}"),
+            Self::Package(message) => write!(f, "package error: {message}"),
+            Self::Font(message) => write!(f, "font error: {message}"),
+            Self::CompileFailed(diagnostics) => {
+                write!(f, "compile failed with {} errors", diagnostics.len())
+            }
+            Self::Other(message) => f.pad(message),
+        }
+    }
+}
+
+impl Serialize for NoleError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let diagnostics = match self {
+            Self::CompileFailed(diagnostics) => Some(diagnostics),
+            _ => None,
+        };
+        let mut state =
+            serializer.serialize_struct("NoleError", 2 + diagnostics.is_some() as usize)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        if let Some(diagnostics) = diagnostics {
+            state.serialize_field("diagnostics", diagnostics)?;
+        }
+        state.end()
+    }
+}
+
+impl<T> From<PoisonError<T>> for NoleError {
+    fn from(_: PoisonError<T>) -> Self {
+        Self::LockPoisoned
+    }
+}
+
+impl From<io::Error> for NoleError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err.to_string().into())
+    }
+}
+
+impl From<FileError> for NoleError {
+    fn from(err: FileError) -> Self {
+        match err {
+            FileError::Package(err) => err.into(),
+            err => Self::Io(err.to_string().into()),
+        }
+    }
+}
+
+impl From<PackageError> for NoleError {
+    fn from(err: PackageError) -> Self {
+        Self::Package(err.to_string().into())
+    }
+}
+
+impl From<EcoString> for NoleError {
+    fn from(message: EcoString) -> Self {
+        Self::Other(message)
+    }
+}
+
+impl From<&str> for NoleError {
+    fn from(message: &str) -> Self {
+        Self::Other(message.into())
+    }
+}
+
+impl From<String> for NoleError {
+    fn from(message: String) -> Self {
+        Self::Other(message.into())
+    }
+}
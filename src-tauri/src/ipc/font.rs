@@ -0,0 +1,67 @@
+use crate::engine::{FontFamilyInfo, FontStats, TypstEngine};
+use crate::error::{NoleError, NoleResult};
+use super::typst::recompile;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::Runtime;
+
+/// Change the global font directories, searched after the workspace's `fonts`
+/// directory and before the system fonts.
+#[tauri::command]
+pub async fn set_font_dirs(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    dirs: Vec<PathBuf>,
+) -> NoleResult<()> {
+    engine.set_font_dirs(dirs)
+}
+
+/// Search the fonts again without restarting, e.g. after installing a font.
+///
+/// The open document is compiled again with the new fonts and emitted as
+/// `typst::recompiled`. Returns the number of fonts found.
+#[tauri::command]
+pub async fn reload_fonts<R: Runtime>(
+    window: tauri::Window<R>,
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    workspace: Option<PathBuf>,
+) -> NoleResult<usize> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || -> NoleResult<usize> {
+        let n_fonts = engine.reload_fonts(workspace.as_deref())?;
+        recompile(&window, &engine);
+        Ok(n_fonts)
+    })
+    .await
+    .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
+
+/// List the discovered font families with their variants.
+///
+/// With a workspace, its `fonts` directory is included.
+#[tauri::command]
+pub async fn list_fonts(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    workspace: Option<PathBuf>,
+) -> NoleResult<Vec<FontFamilyInfo>> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || -> NoleResult<Vec<FontFamilyInfo>> {
+        let core = match workspace {
+            Some(workspace) => engine.core(&workspace)?,
+            None => engine.current_core()?,
+        };
+        Ok(core.font_families())
+    })
+    .await
+    .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
+
+/// Get statistics of the loaded fonts, for diagnostics.
+#[tauri::command]
+pub async fn font_stats(engine: tauri::State<'_, Arc<TypstEngine>>) -> NoleResult<FontStats> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || -> NoleResult<FontStats> {
+        Ok(engine.current_core()?.font_stats())
+    })
+    .await
+    .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
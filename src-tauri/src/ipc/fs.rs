@@ -1,15 +1,16 @@
 use std::path::PathBuf;
 use trash;
+use crate::error::{NoleError, NoleResult};
 
 /// Returns whether it without errors.
 #[tauri::command]
-pub async fn delete(path: PathBuf) -> Result<(), String> {
-    trash::delete(path).map_err(|err| err.to_string())
+pub async fn delete(path: PathBuf) -> NoleResult<()> {
+    trash::delete(path).map_err(|err| NoleError::Io(err.to_string().into()))
 }
 
 /// Return a path that does not exist. If the path exists, the path will be appended with a number.
 #[tauri::command]
-pub async fn get_available_path(path: PathBuf) -> Result<PathBuf, String> {
+pub async fn get_available_path(path: PathBuf) -> NoleResult<PathBuf> {
     let mut i = 0;
     let name = path.clone();
     let mut path = path;
@@ -1,7 +1,11 @@
 mod typst;
 mod fs;
 mod clipboard;
+mod package;
+mod font;
 
 pub use typst::*;
 pub use fs::*;
-pub use clipboard::*;
\ No newline at end of file
+pub use clipboard::*;
+pub use package::*;
+pub use font::*;
\ No newline at end of file
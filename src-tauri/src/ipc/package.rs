@@ -0,0 +1,48 @@
+use crate::engine::{PackageInfo, TypstEngine};
+use crate::error::{NoleError, NoleResult};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use typst::syntax::PackageSpec;
+
+/// List the packages available on disk, including those of the workspace.
+#[tauri::command]
+pub async fn list_packages(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    workspace: Option<PathBuf>,
+) -> NoleResult<Vec<PackageInfo>> {
+    Ok(engine.packages.list(workspace.as_deref()))
+}
+
+/// Download a package like `@preview/example:0.1.0` from the registry.
+#[tauri::command]
+pub async fn install_package(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    spec: String,
+) -> NoleResult<PathBuf> {
+    let spec = PackageSpec::from_str(&spec).map_err(NoleError::Package)?;
+    let packages = engine.packages.clone();
+    tauri::async_runtime::spawn_blocking(move || packages.install(&spec).map_err(NoleError::from))
+        .await
+        .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
+
+/// Remove a package from disk.
+#[tauri::command]
+pub async fn remove_package(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    spec: String,
+) -> NoleResult<()> {
+    let spec = PackageSpec::from_str(&spec).map_err(NoleError::Package)?;
+    Ok(engine.packages.remove(&spec)?)
+}
+
+/// Change the registry packages are downloaded from.
+#[tauri::command]
+pub async fn set_package_registry(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    url: String,
+) -> NoleResult<()> {
+    engine.packages.set_registry(url);
+    Ok(())
+}
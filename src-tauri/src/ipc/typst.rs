@@ -1,19 +1,27 @@
-use crate::engine::{NoleWorld, TypstEngine};
+use crate::engine::{
+    export_batch, export_images as export_images_of, export_pdf, find_entries,
+    find_font_issues, parse_pages, display_path, BatchSummary, DependencyWatcher, ExportFormat,
+    FontIssue, NoleWorld, TypstDiagnostic, TypstEngine, TypstLocation, DEFAULT_PPI,
+};
+use crate::error::{NoleError, NoleResult};
 use base64::{engine::general_purpose, Engine as _};
-use chrono::{Datelike, Timelike};
 use serde::Serialize;
 use serde_repr::Serialize_repr;
-use std::fs;
-use std::ops::Range;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc};
 use tauri::Runtime;
-use typst::diag::{EcoString, Severity};
-use typst::eval::Tracer;
-use typst::foundations::Datetime;
+use typst::introspection::{CounterKey, CounterUpdate, Meta};
+use typst::layout::{Abs, Frame, FrameItem, Point};
+use typst::model::{Document, Numbering};
+use typst::syntax::LinkedNode;
+use typst::visualize::Color;
 use typst::World;
-use typst::{diag::StrResult, visualize::Color};
-use typst_ide::{Completion, CompletionKind};
+use typst_ide::{Completion, CompletionKind, Jump, Tooltip};
+
+/// The extensions of the image formats typst can read.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg"];
 
 #[derive(Serialize_repr, Debug)]
 #[repr(u8)]
@@ -58,27 +66,106 @@ impl From<Completion> for TypstCompletion {
     }
 }
 
+/// The tooltip of the hovered code.
+#[derive(Serialize, Debug)]
+pub struct TypstHoverResponse {
+    pub kind: TypstHoverKind,
+    /// Markdown for text, typst code for code.
+    pub content: String,
+}
+
 #[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TypstHoverKind {
+    Text,
+    Code,
+}
+
+impl From<Tooltip> for TypstHoverResponse {
+    fn from(value: Tooltip) -> Self {
+        match value {
+            Tooltip::Text(text) => Self { kind: TypstHoverKind::Text, content: text.to_string() },
+            Tooltip::Code(code) => Self { kind: TypstHoverKind::Code, content: code.to_string() },
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
 pub struct TypstCompileResponse {
     pub updated_idx: Vec<usize>,
     pub n_pages: usize,
     pub width: f64,
     pub height: f64,
+    /// The size of every page, pages may differ in size.
+    pub pages: Vec<TypstPage>,
+    pub warnings: Vec<TypstDiagnostic>,
+    /// The generation of the compile request, newer requests have larger ones.
+    pub generation: u64,
 }
 
 #[derive(Serialize, Clone, Debug)]
-#[serde(rename_all = "snake_case")]
-pub enum TypstDiagnosticSeverity {
-    Error,
-    Warning,
+pub struct TypstPage {
+    pub width: f64,
+    pub height: f64,
+    /// The one-based page number.
+    pub number: usize,
+    /// The value of the page counter formatted with the page's numbering, e.g.
+    /// `iii`.
+    ///
+    /// Numberings with a function can't be applied outside of a compilation,
+    /// for them the plain counter value is used.
+    pub label: String,
+}
+
+impl TypstPage {
+    /// `counter` is the value of the page counter on the page.
+    pub fn new(index: usize, frame: &Frame, counter: usize) -> Self {
+        let number = index + 1;
+        let label = match page_numbering(frame) {
+            Some(Numbering::Pattern(pattern)) => pattern.apply(&[counter]).to_string(),
+            _ => counter.to_string(),
+        };
+        Self {
+            width: frame.width().to_pt(),
+            height: frame.height().to_pt(),
+            number,
+            label,
+        }
+    }
 }
 
+/// A file used by the document.
 #[derive(Serialize, Clone, Debug)]
-pub struct TypstDiagnostic {
-    pub range: Range<usize>,
-    pub severity: TypstDiagnosticSeverity,
-    pub message: String,
-    pub hints: Vec<String>,
+pub struct TypstDependency {
+    /// The workspace-relative path, prefixed with the package spec for files
+    /// in packages.
+    pub path: String,
+    /// The path of the file on the system.
+    pub system_path: Option<PathBuf>,
+}
+
+/// The files used by the last compilation, grouped by kind.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct TypstDependencies {
+    /// Typst files, the main file included.
+    pub sources: Vec<TypstDependency>,
+    pub images: Vec<TypstDependency>,
+    /// Other files read by the document, like bibliographies or json.
+    pub data: Vec<TypstDependency>,
+    /// The specs of the packages the document imports.
+    pub packages: Vec<String>,
+}
+
+/// Where a click into the preview leads.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypstJump {
+    /// The source of the clicked text.
+    Source(TypstLocation),
+    /// The target of a clicked link to a website.
+    Url { url: String },
+    /// The target of a clicked link into the document, `page` is zero-based.
+    Position { page: usize, x: f64, y: f64 },
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -88,28 +175,22 @@ pub struct TypstRenderResponse {
     pub height: f64,
 }
 
+/// Complete the code at a char offset of the content of the file at `path`.
 #[tauri::command]
 pub async fn autocomplete(
     engine: tauri::State<'_, Arc<TypstEngine>>,
+    path: PathBuf,
     content: String,
     offset: usize,
     explicit: bool,
-) -> StrResult<TypstCompleteResponse> {
-    let world = engine.world_cache.lock().expect("get world lock failed!");
-    let world = world.as_ref().ok_or("World not initialized!".to_string())?;
-    world.virtual_source(world.main(), content.to_string())?;
-    let source = world.source(world.main()).map_err(|err| err.to_string())?;
-
-    // recalc offest for chinese character
-    let offset = content
-        .char_indices()
-        .nth(offset)
-        .map(|a| a.0)
-        .unwrap_or(content.len());
+) -> NoleResult<TypstCompleteResponse> {
+    let snapshot = engine.snapshot()?;
+    let world = snapshot.edit(snapshot.id(&path)?, &content);
+    let offset = byte_offset(&content, offset);
 
     let (completed_offset, completions) =
-        typst_ide::autocomplete(world, None, &source, offset, explicit)
-            .ok_or("Failed to perform autocomplete".to_string())?;
+        typst_ide::autocomplete(&world, None, world.edited(), offset, explicit)
+            .ok_or("Failed to perform autocomplete")?;
     // recalc offest for chinese character
     let completed_char_offset = content.clone()[..completed_offset].chars().count();
 
@@ -120,8 +201,34 @@ pub async fn autocomplete(
     // completions
 }
 
+/// Get the tooltip at a char offset of the content of the file at `path`, like
+/// the value of an expression or the docs of a function.
+///
+/// Returns none if there is nothing to show.
+#[tauri::command]
+pub async fn hover(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    path: PathBuf,
+    content: String,
+    offset: usize,
+) -> NoleResult<Option<TypstHoverResponse>> {
+    let snapshot = engine.snapshot()?;
+    let world = snapshot.edit(snapshot.id(&path)?, &content);
+    let offset = byte_offset(&content, offset);
+
+    // The document resolves labels and references.
+    let document = engine.document_cache.read()?;
+    let tooltip = typst_ide::tooltip(&world, document.as_ref(), world.edited(), offset);
+    Ok(tooltip.map(TypstHoverResponse::from))
+}
+
 /// Compile a single time.
 ///
+/// Compilations run on the compile queue of the engine. A request that is
+/// superseded by a newer one before it starts fails without compiling. With
+/// `init`, the next compilation that runs starts from a fresh world, unless it
+/// compiles the same entry as the current world.
+///
 /// Returns whether it compiled without errors.
 #[tauri::command]
 pub async fn compile<R: Runtime>(
@@ -131,29 +238,125 @@ pub async fn compile<R: Runtime>(
     path: PathBuf,
     content: String,
     init: bool,
-) -> StrResult<TypstCompileResponse> {
-    let start = std::time::Instant::now();
+) -> NoleResult<TypstCompileResponse> {
+    let engine = engine.inner().clone();
     if init {
-        engine.reset().map_err(|err| err.to_string())?;
+        engine.init_requested.store(true, Ordering::SeqCst);
     }
-    let mut world = engine
-        .world_cache
-        .lock()
-        .map_err(|_| "Get world lock failed!")?;
-    if world.is_none() || world.as_ref().ok_or("Unknow world cache stat.")?.input() != &path {
-        *world = Some(NoleWorld::new(workspace, path, engine.core.clone())?);
+    let (sender, receiver) = mpsc::channel();
+    engine.queue.submit({
+        let engine = engine.clone();
+        move |generation| {
+            let result = recover(&engine, || {
+                compile_job(&window, &engine, generation, workspace, path, content)
+            });
+            let _ = sender.send(result);
+        }
+    });
+    tauri::async_runtime::spawn_blocking(move || receiver.recv())
+        .await
+        .map_err(|err| NoleError::Other(err.to_string().into()))?
+        .unwrap_or(Err(NoleError::Superseded))
+}
+
+/// Compile the workspace on the compile queue.
+fn compile_job<R: Runtime>(
+    window: &tauri::Window<R>,
+    engine: &Arc<TypstEngine>,
+    generation: u64,
+    workspace: PathBuf,
+    path: PathBuf,
+    content: String,
+) -> NoleResult<TypstCompileResponse> {
+    let start = std::time::Instant::now();
+    let init = engine.init_requested.swap(false, Ordering::SeqCst);
+    let mut world = engine.world_cache.lock()?;
+    // Only rebuild the world when the entrypoint changes, so switching between
+    // the files of one document keeps the caches warm, even though every
+    // opened file initializes the editor again.
+    let entry = engine.entry(&workspace, &path);
+    let core = engine.core(&workspace)?;
+    let stale = match world.as_ref() {
+        Some(world) => {
+            entry.canonicalize().ok().as_ref() != Some(world.input())
+                || workspace.canonicalize().ok().as_deref() != Some(world.root())
+        }
+        None => true,
+    };
+    if stale {
+        if init {
+            // Don't show the document of another entry meanwhile.
+            *engine.snapshot.write()? = None;
+            *engine.document_cache.write()? = None;
+        }
+        *world = Some(NoleWorld::new(workspace, entry, core)?);
     }
-    let world = world.as_mut().ok_or("World initialize failed")?;
-    // world.reset(); todo: currently, nole only support single file opened, so we don't need to reset the world
-    world.virtual_source(world.main(), content.clone())?;
+    let world = world.as_mut().ok_or(NoleError::WorldNotInitialized)?;
+    // The font directories changed, keep the unsaved files.
+    if !Arc::ptr_eq(world.core(), &core) {
+        world.set_core(core);
+    }
+    world.reset();
+    let id = world.id(&path)?;
+    world.set_overlay(id, content)?;
+    compile_world(window, engine, world, generation, start)
+}
 
-    let mut tracer = Tracer::new();
-    let result = typst::compile(world, &mut tracer);
+/// Compile the world again after its dependencies changed on disk.
+///
+/// Emits the response as `typst::recompiled`.
+pub(crate) fn recompile<R: Runtime>(window: &tauri::Window<R>, engine: &Arc<TypstEngine>) {
+    let window = window.clone();
+    let engine_ = engine.clone();
+    engine.queue.submit(move |generation| {
+        let start = std::time::Instant::now();
+        let result = recover(&engine_, || {
+            let mut world = engine_.world_cache.lock()?;
+            let world = world.as_mut().ok_or(NoleError::WorldNotInitialized)?;
+            world.reset();
+            compile_world(&window, &engine_, world, generation, start)
+        });
+        if let Ok(response) = result {
+            let _ = window.emit("typst::recompiled", response);
+        }
+    });
+}
+
+/// Run a job of the compile queue, turning a panic into an error.
+///
+/// The world the panic left behind is dropped, so the next compilation starts
+/// from a fresh one instead of failing on the poisoned lock.
+fn recover<T>(engine: &TypstEngine, job: impl FnOnce() -> NoleResult<T>) -> NoleResult<T> {
+    panic::catch_unwind(AssertUnwindSafe(job)).unwrap_or_else(|payload| {
+        let _ = engine.reset();
+        Err(NoleError::panicked(payload))
+    })
+}
+
+/// Compile the prepared world, cache the document and watch its dependencies.
+///
+/// The world stays locked while compiling, queries of the editor use the
+/// snapshot taken afterwards.
+fn compile_world<R: Runtime>(
+    window: &tauri::Window<R>,
+    engine: &Arc<TypstEngine>,
+    world: &mut NoleWorld,
+    generation: u64,
+    start: std::time::Instant,
+) -> NoleResult<TypstCompileResponse> {
+    let result = world.compile();
     comemo::evict(1);
+    // Watching is optional, a compilation must not fail on it.
+    if let Err(err) = watch_dependencies(window, engine, world) {
+        eprintln!("failed to watch the dependencies: {err}");
+    }
+    engine.record_sources(world)?;
+    let snapshot = world.snapshot();
+    *engine.snapshot.write()? = Some(Arc::new(snapshot));
 
     match result {
         // Export the SVG.
-        Ok(document) => {
+        Ok((document, warnings)) => {
             let duration = start.elapsed();
             println!("Compile duration: {:?}", duration);
             let mut updated_idx: Vec<usize> = vec![];
@@ -164,103 +367,275 @@ pub async fn compile<R: Runtime>(
                 }
                 updated_idx.push(i);
             }
-            let first_page = &document.pages[0];
-            let width = first_page.width();
-            let height = first_page.height();
             let n_pages = document.pages.len();
-            engine
-                .document_cache
-                .write()
-                .map_err(|_| "Write document failed!")?
-                .replace(document);
+            let pages: Vec<TypstPage> = document
+                .pages
+                .iter()
+                .zip(page_counters(&document))
+                .enumerate()
+                .map(|(i, (frame, counter))| TypstPage::new(i, frame, counter))
+                .collect();
+            let (width, height) = pages
+                .first()
+                .map(|page| (page.width, page.height))
+                .unwrap_or_default();
+            engine.document_cache.write()?.replace(document);
+
+            // Drop the errors of the last compilation before reporting warnings.
+            let _ = window.emit("typst::diagnostics_cleared", ());
+            if !warnings.is_empty() {
+                let _ = window.emit("typst::compile", warnings.clone());
+            }
 
             Ok(TypstCompileResponse {
                 updated_idx,
                 n_pages,
-                width: width.to_pt(),
-                height: height.to_pt(),
+                width,
+                height,
+                pages,
+                warnings,
+                generation,
             })
         }
 
         // Print diagnostics.
-        Err(diagnostics) => {
+        Err(NoleError::CompileFailed(diagnostics)) => {
             println!(
                 "compilation failed with {:?} diagnostics",
                 diagnostics.len()
             );
+            let _ = window.emit("typst::compile", diagnostics.clone());
+            Err(NoleError::CompileFailed(diagnostics))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Watch the files the last compilation read from disk.
+///
+/// Emits `typst::dependency_changed` when one changes, and recompiles if
+/// enabled.
+fn watch_dependencies<R: Runtime>(
+    window: &tauri::Window<R>,
+    engine: &Arc<TypstEngine>,
+    world: &NoleWorld,
+) -> NoleResult<()> {
+    let mut watcher = engine.watcher.lock()?;
+    if watcher.is_none() {
+        let window = window.clone();
+        let engine = engine.clone();
+        *watcher = Some(DependencyWatcher::new(move |paths| {
+            let _ = window.emit("typst::dependency_changed", paths);
+            if engine.auto_recompile.load(Ordering::SeqCst) {
+                recompile(&window, &engine);
+            }
+        })?);
+    }
+    if let Some(watcher) = watcher.as_mut() {
+        watcher.update(world.dependencies());
+    }
+    Ok(())
+}
 
-            let source = world.source(world.main());
-            let diagnostics: Vec<TypstDiagnostic> = match source {
-                Ok(source) => diagnostics
-                    .iter()
-                    .filter(|d| d.span.id() == Some(world.main()))
-                    .filter_map(|d| {
-                        let span = source.find(d.span)?;
-                        let range = span.range();
-                        let start = content[..range.start].chars().count();
-                        let size = content[range.start..range.end].chars().count();
-
-                        let message = d.message.to_string();
-                        Some(TypstDiagnostic {
-                            range: start..start + size,
-                            severity: match d.severity {
-                                Severity::Error => TypstDiagnosticSeverity::Error,
-                                Severity::Warning => TypstDiagnosticSeverity::Warning,
-                            },
-                            message,
-                            hints: d.hints.iter().map(|hint| hint.to_string()).collect(),
-                        })
-                    })
-                    .collect(),
-                Err(_) => vec![],
-            };
-            let _ = window.emit("typst::compile", diagnostics);
-            Err(EcoString::from("Compile failed!"))
+/// Get the files the last compilation used, so the file tree can mark them.
+#[tauri::command]
+pub async fn dependencies(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+) -> NoleResult<TypstDependencies> {
+    let snapshot = engine.snapshot()?;
+    let mut dependencies = TypstDependencies::default();
+    for file in snapshot.accessed() {
+        if let Some(spec) = file.id.package() {
+            let spec = spec.to_string();
+            if !dependencies.packages.contains(&spec) {
+                dependencies.packages.push(spec);
+            }
+            continue;
+        }
+        let dependency = TypstDependency {
+            path: display_path(file.id),
+            system_path: file.path.clone(),
+        };
+        let extension = file
+            .id
+            .vpath()
+            .as_rootless_path()
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        // A file can be both imported and read, it is listed for each use.
+        if file.source {
+            dependencies.sources.push(dependency.clone());
+        }
+        if file.file {
+            if extension.is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str())) {
+                dependencies.images.push(dependency);
+            } else {
+                dependencies.data.push(dependency);
+            }
         }
     }
-    // todo!()
+    dependencies.sources.sort_by(|a, b| a.path.cmp(&b.path));
+    dependencies.images.sort_by(|a, b| a.path.cmp(&b.path));
+    dependencies.data.sort_by(|a, b| a.path.cmp(&b.path));
+    dependencies.packages.sort();
+    Ok(dependencies)
+}
+
+/// Recompile automatically when a dependency changes on disk.
+#[tauri::command]
+pub async fn set_auto_recompile(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    enabled: bool,
+) -> NoleResult<()> {
+    engine.auto_recompile.store(enabled, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Shadow a file of the workspace with the unsaved content of the editor.
+///
+/// Waits for a running compilation, as it changes the world.
+#[tauri::command]
+pub async fn set_overlay(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    path: PathBuf,
+    content: String,
+) -> NoleResult<()> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || -> NoleResult<()> {
+        let world = engine.world_cache.lock()?;
+        let world = world.as_ref().ok_or(NoleError::WorldNotInitialized)?;
+        let id = world.id(&path)?;
+        Ok(world.set_overlay(id, content)?)
+    })
+    .await
+    .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
+
+/// Drop the unsaved content of a file, or of all files if no path is given.
+///
+/// Waits for a running compilation, as it changes the world.
+#[tauri::command]
+pub async fn clear_overlay(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    path: Option<PathBuf>,
+) -> NoleResult<()> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || -> NoleResult<()> {
+        let world = engine.world_cache.lock()?;
+        let world = world.as_ref().ok_or(NoleError::WorldNotInitialized)?;
+        match path {
+            Some(path) => world.clear_overlay(world.id(&path)?),
+            None => world.clear_overlays(),
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
+
+/// Pin the file that is compiled for every file of the workspace.
+///
+/// Unpins the workspace if no path is given.
+#[tauri::command]
+pub async fn set_entry(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    workspace: PathBuf,
+    path: Option<PathBuf>,
+) -> NoleResult<()> {
+    engine.set_entry(workspace, path)
 }
 
 /// reset the world and document at editor mount.
 #[tauri::command]
-pub async fn reset(engine: tauri::State<'_, Arc<TypstEngine>>) -> Result<(), String> {
-    engine.reset().map_err(|err| err.to_string())
+pub async fn reset(engine: tauri::State<'_, Arc<TypstEngine>>) -> NoleResult<()> {
+    engine.reset()
 }
 
 /// render the svg of the page.
 #[tauri::command]
-pub async fn svg(engine: tauri::State<'_, Arc<TypstEngine>>, page: usize) -> Result<String, String> {
-    let document = engine
-        .document_cache
-        .read()
-        .map_err(|_| "Read document failed!")?;
+pub async fn svg(engine: tauri::State<'_, Arc<TypstEngine>>, page: usize) -> NoleResult<String> {
+    let document = engine.document_cache.read()?;
     let now = std::time::Instant::now();
-    let frame = document.as_ref().ok_or("Document not initialized!")?.pages[page].clone();
-    let svg = typst_svg::svg(&frame);
+    let frame = page_frame(document.as_ref(), page)?;
+    let svg = typst_svg::svg(frame);
     let elapsed = now.elapsed();
     println!("Render page {:?} duration: {:?}", page, elapsed);
     Ok(svg)
 }
 
+/// Find what was clicked at a point of a page, in pt from its top left.
+///
+/// Returns the source of clicked text, the url of a clicked link, or the
+/// position a clicked internal link leads to. None if nothing is there.
+#[tauri::command]
+pub async fn jump(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    page: usize,
+    x: f64,
+    y: f64,
+) -> NoleResult<Option<TypstJump>> {
+    let world = engine.snapshot()?;
+    let world = world.as_ref();
+    let document = engine.document_cache.read()?;
+    let frame = page_frame(document.as_ref(), page)?;
+    let document = document.as_ref().ok_or(NoleError::DocumentNotInitialized)?;
+
+    let click = Point::new(Abs::pt(x), Abs::pt(y));
+    let jump = match typst_ide::jump_from_click(world, document, frame, click) {
+        Some(Jump::Source(id, offset)) => {
+            // Select the clicked text instead of placing a cursor.
+            let range = world
+                .source(id)
+                .ok()
+                .and_then(|source| {
+                    let leaf = LinkedNode::new(source.root()).leaf_at(offset)?;
+                    Some(leaf.range())
+                })
+                .unwrap_or(offset..offset);
+            Some(TypstJump::Source(TypstLocation::from_range(world, id, range)))
+        }
+        Some(Jump::Url(url)) => Some(TypstJump::Url { url: url.to_string() }),
+        Some(Jump::Position(position)) => Some(TypstJump::Position {
+            page: position.page.get() - 1,
+            x: position.point.x.to_pt(),
+            y: position.point.y.to_pt(),
+        }),
+        None => None,
+    };
+    Ok(jump)
+}
+
+/// Find the text of the last compilation that rendered as boxes or with a
+/// fallback font.
+///
+/// Walks every glyph of the document, so it is left to the frontend when to
+/// check instead of running after every compilation.
+#[tauri::command]
+pub async fn font_issues(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+) -> NoleResult<Vec<FontIssue>> {
+    let world = engine.snapshot()?;
+    let document = engine.document_cache.read()?;
+    let document = document.as_ref().ok_or(NoleError::DocumentNotInitialized)?;
+    Ok(find_font_issues(world.as_ref(), document))
+}
+
 /// Returns whether it render without errors.
 #[tauri::command]
 pub async fn render(
     engine: tauri::State<'_, Arc<TypstEngine>>,
     page: usize,
     scale: f32,
-) -> Result<TypstRenderResponse, String> {
-    let document = engine
-        .document_cache
-        .read()
-        .map_err(|_| "Read document failed!")?;
+) -> NoleResult<TypstRenderResponse> {
+    let document = engine.document_cache.read()?;
     let now = std::time::Instant::now();
-    let frame = document.as_ref().ok_or("Document not initialized!")?.pages[page].clone();
-    let bmp = typst_render::render(&frame, scale, Color::WHITE);
+    let frame = page_frame(document.as_ref(), page)?;
+    let bmp = typst_render::render(frame, scale, Color::WHITE);
     let elapsed = now.elapsed();
     println!("Render page {:?} duration: {:?}", page, elapsed);
     return bmp
         .encode_png()
-        .map_err(|err| err.to_string())
+        .map_err(|err| NoleError::Io(err.to_string().into()))
         .map(|image| {
             let b64 = general_purpose::STANDARD.encode(image);
             TypstRenderResponse {
@@ -272,39 +647,203 @@ pub async fn render(
 }
 
 /// Returns whether it without errors.
+///
+/// Compiles the file from disk, so a failed or missing preview compilation
+/// does not matter. Exports the entry of the open workspace if no `input` is
+/// given.
 #[tauri::command]
 pub async fn export(
     engine: tauri::State<'_, Arc<TypstEngine>>,
     id: String,
     path: PathBuf,
-) -> Result<(), String> {
-    let document = engine
-        .document_cache
-        .read()
-        .map_err(|_| "Read document failed!")?;
-    let timer = std::time::Instant::now();
-
-    let pdf = typst_pdf::pdf(
-        document.as_ref().ok_or("can not get ducument.")?,
-        Some(&id),
-        now(),
-    );
-    let elapsed = timer.elapsed();
-    println!("Export pdf duration: {:?}", elapsed);
-    fs::write(path, pdf)
-        .map_err(|err| err.to_string())
-        .map(|_| ())
-}
-
-/// Get the current date and time in UTC.
-fn now() -> Option<Datetime> {
-    let now = chrono::Local::now().naive_utc();
-    Datetime::from_ymd_hms(
-        now.year(),
-        now.month().try_into().ok()?,
-        now.day().try_into().ok()?,
-        now.hour().try_into().ok()?,
-        now.minute().try_into().ok()?,
-        now.second().try_into().ok()?,
-    )
+    workspace: Option<PathBuf>,
+    input: Option<PathBuf>,
+) -> NoleResult<()> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || -> NoleResult<()> {
+        let (document, _) = compile_for_export(&engine, workspace, input)?;
+        let timer = std::time::Instant::now();
+        export_pdf(&document, &id, &path)?;
+        println!("Export pdf duration: {:?}", timer.elapsed());
+        Ok(())
+    })
+    .await
+    .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
+
+/// Export pages of the document as png or svg images into a directory.
+///
+/// `pages` selects pages like `1-3,7`, all pages if omitted. `template` names
+/// the files, e.g. `{stem}-{page}.png`.
+///
+/// Returns the paths of the written images.
+#[tauri::command]
+pub async fn export_images(
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    format: ExportFormat,
+    dir: PathBuf,
+    pages: Option<String>,
+    template: Option<String>,
+    ppi: Option<f32>,
+    workspace: Option<PathBuf>,
+    input: Option<PathBuf>,
+) -> NoleResult<Vec<PathBuf>> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let (document, stem) = compile_for_export(&engine, workspace, input)?;
+        let pages = parse_pages(pages.as_deref().unwrap_or_default(), document.pages.len())?;
+        export_images_of(
+            &document,
+            format,
+            &pages,
+            ppi.unwrap_or(DEFAULT_PPI),
+            &dir,
+            &template.unwrap_or_else(|| format.template()),
+            &stem,
+        )
+    })
+    .await
+    .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
+
+/// Export every matching file of the workspace as pdf, in parallel.
+///
+/// `include` and `exclude` are globs relative to the workspace, all `.typ`
+/// files are included by default. The pdfs are written below `out_dir`, next
+/// to their sources if omitted. Emits `typst::batch_export` once per file.
+#[tauri::command]
+pub async fn export_workspace<R: Runtime>(
+    window: tauri::Window<R>,
+    engine: tauri::State<'_, Arc<TypstEngine>>,
+    workspace: PathBuf,
+    out_dir: Option<PathBuf>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+) -> NoleResult<BatchSummary> {
+    let engine = engine.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || -> NoleResult<BatchSummary> {
+        let workspace = workspace.canonicalize()?;
+        let core = engine.core(&workspace)?;
+        let out_dir = out_dir.unwrap_or_else(|| workspace.clone());
+        let entries = find_entries(
+            &workspace,
+            &include.unwrap_or_default(),
+            &exclude.unwrap_or_default(),
+        )?;
+        Ok(export_batch(core, &workspace, &out_dir, &entries, |progress| {
+            let _ = window.emit("typst::batch_export", progress);
+        }))
+    })
+    .await
+    .map_err(|err| NoleError::Other(err.to_string().into()))?
+}
+
+/// Compile a file from disk for export.
+///
+/// Falls back to the entry of the open world if no `input` is given. The
+/// workspace defaults to the root of the open world, or to the directory of
+/// the input if it is outside of it.
+///
+/// Returns the document and the file stem of the input.
+fn compile_for_export(
+    engine: &TypstEngine,
+    workspace: Option<PathBuf>,
+    input: Option<PathBuf>,
+) -> NoleResult<(Document, String)> {
+    let (root, input) = match input {
+        Some(input) => {
+            let root = workspace
+                .or_else(|| open_root(engine, &input))
+                .or_else(|| input.parent().map(Path::to_path_buf))
+                .unwrap_or_default();
+            (root, input)
+        }
+        None => {
+            let world = engine.snapshot()?;
+            (world.root().to_path_buf(), world.input().clone())
+        }
+    };
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "page".to_string());
+    Ok((engine.compile_file(root, input)?, stem))
+}
+
+/// The root of the open world, if it contains the input.
+fn open_root(engine: &TypstEngine, input: &Path) -> Option<PathBuf> {
+    let snapshot = engine.snapshot().ok()?;
+    let input = input.canonicalize().ok()?;
+    input.starts_with(snapshot.root()).then(|| snapshot.root().to_path_buf())
+}
+
+/// Convert a char offset of the editor into a byte offset of the content, so
+/// chinese characters are counted right.
+fn byte_offset(content: &str, offset: usize) -> usize {
+    content
+        .char_indices()
+        .nth(offset)
+        .map(|(i, _)| i)
+        .unwrap_or(content.len())
+}
+
+/// Get a page of the cached document.
+fn page_frame(document: Option<&Document>, page: usize) -> NoleResult<&Frame> {
+    let document = document.ok_or(NoleError::DocumentNotInitialized)?;
+    document.pages.get(page).ok_or(NoleError::PageOutOfRange {
+        page,
+        n_pages: document.pages.len(),
+    })
+}
+
+/// The value of the page counter on every page.
+///
+/// Applies the updates of the page counter in the pages like typst does when
+/// laying them out, so a numbering that restarts at 1 after the front matter
+/// is followed. Updates with a function can't be applied outside of a
+/// compilation and are skipped.
+fn page_counters(document: &Document) -> Vec<usize> {
+    let mut counter = 1;
+    document
+        .pages
+        .iter()
+        .map(|frame| {
+            update_page_counter(frame, &mut counter);
+            let value = counter;
+            counter += 1;
+            value
+        })
+        .collect()
+}
+
+/// Apply the updates of the page counter in a frame.
+fn update_page_counter(frame: &Frame, counter: &mut usize) {
+    for (_, item) in frame.items() {
+        match item {
+            FrameItem::Group(group) => update_page_counter(&group.frame, counter),
+            FrameItem::Meta(Meta::Elem(elem), _) => {
+                // The updates of all counters are located in the frames.
+                let key = elem.field("key").and_then(|key| key.cast::<CounterKey>().ok());
+                if key != Some(CounterKey::Page) {
+                    continue;
+                }
+                match elem.field("update").and_then(|update| update.cast().ok()) {
+                    Some(CounterUpdate::Set(state)) => {
+                        *counter = state.0.first().copied().unwrap_or(1);
+                    }
+                    Some(CounterUpdate::Step(level)) if level.get() == 1 => *counter += 1,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The numbering the page was laid out with.
+fn page_numbering(frame: &Frame) -> Option<Numbering> {
+    frame.items().find_map(|(_, item)| match item {
+        FrameItem::Meta(Meta::PageNumbering(numbering), _) => numbering.clone().cast().ok(),
+        _ => None,
+    })
 }
@@ -1,16 +1,24 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod cli;
 mod engine;
+mod error;
 mod ipc;
 
 use engine::TypstEngine;
+use std::process::ExitCode;
 use std::sync::Arc;
 use tauri::Manager;
 
-fn main() {
-    // let engine = Arc::new(TypstEngine::new());
+fn main() -> ExitCode {
+    // `nole compile`, `nole watch` and `nole export` run without a window.
+    if cli::requested() {
+        return cli::run();
+    }
+
     let engine = Arc::new(TypstEngine::new());
+    let packages = engine.clone();
     // initialize the custom invoke system as a HTTP server, allowing the given origins to access it.
     let http = tauri_invoke_http::Invoke::new(if cfg!(feature = "custom-protocol") {
         ["tauri://localhost"]
@@ -21,6 +29,10 @@ fn main() {
     .invoke_system(http.initialization_script(), http.responder())
         .setup(move |app| {
             http.start(app.handle());
+            let handle = app.handle();
+            packages.packages.on_progress(move |progress| {
+                let _ = handle.emit_all("typst::package", progress);
+            });
             #[cfg(debug_assertions)] // only include this code on debug builds
             {
                 let window = app.get_window("main").unwrap();
@@ -34,14 +46,33 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             ipc::reset,
             ipc::compile,
+            ipc::set_overlay,
+            ipc::clear_overlay,
+            ipc::set_entry,
+            ipc::set_auto_recompile,
+            ipc::dependencies,
             ipc::svg,
             ipc::render,
+            ipc::jump,
+            ipc::font_issues,
             ipc::autocomplete,
+            ipc::hover,
             ipc::export,
+            ipc::export_images,
+            ipc::export_workspace,
             ipc::delete,
             ipc::get_available_path,
             ipc::paste_image,
+            ipc::list_packages,
+            ipc::install_package,
+            ipc::remove_package,
+            ipc::set_package_registry,
+            ipc::set_font_dirs,
+            ipc::reload_fonts,
+            ipc::list_fonts,
+            ipc::font_stats,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
+    ExitCode::SUCCESS
 }
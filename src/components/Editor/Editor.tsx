@@ -73,7 +73,12 @@ const Editor: React.FC = () => {
           ],
         });
         if (exportPath === null) return Promise.reject();
-        exportPDF(currentFile.path, exportPath)
+        exportPDF(
+          currentFile.path,
+          exportPath,
+          window.nole.workspace(),
+          currentFile.path
+        )
           .then(() =>
             window.nole.notify.info({
               content: "Exported at " + exportPath,
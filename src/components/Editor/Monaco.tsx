@@ -7,6 +7,7 @@ import { fetchContent, autosave } from "./utils";
 import { NoleFile } from "../../lib/file";
 import { asyncThrottle, debounce } from "../../lib/utils";
 import { TypstCompileResult, TypstDiagnostic, compile } from "../../ipc/typst";
+import { isNoleError } from "../../ipc/error";
 import * as monaco from "monaco-editor";
 import EditorWorker from "monaco-editor/esm/vs/editor/editor.worker?worker";
 import { listen } from "@tauri-apps/api/event";
@@ -36,6 +37,19 @@ const Monaco: React.FC<MonacoProps> = ({
   const [debounceCancelFn, setDebounceCancelFn] = useState<(() => void) | null>(
     null
   );
+  // the generation of the newest compilation shown, older results are dropped
+  const generationRef = useRef(0);
+
+  const showCompiled = useCallback((document: TypstCompileResult) => {
+    if (document.generation < generationRef.current) return;
+    generationRef.current = document.generation;
+    if (document.updated_idx.length === 0) {
+      onStateChanged?.(compileStatus.idle);
+    } else {
+      onCompiled?.(document);
+      onStateChanged?.(compileStatus.done);
+    }
+  }, []);
 
   const compileThrottled = useCallback(
     asyncThrottle(async (init: boolean = false) => {
@@ -51,18 +65,13 @@ const Monaco: React.FC<MonacoProps> = ({
         init
       ).catch((error) => {
         console.debug(error);
+        // a newer compilation is already on its way
+        if (isNoleError(error, "superseded")) return;
         onStateChanged?.(compileStatus.error);
       });
 
       if (!document) return;
-      if (document.updated_idx.length === 0) {
-        onStateChanged?.(compileStatus.idle);
-      } else {
-        onCompiled?.(document);
-        onStateChanged?.(compileStatus.done);
-      }
-      // remove all markers
-      monaco.editor.setModelMarkers(model, "owner", []);
+      showCompiled(document);
     }),
     []
   );
@@ -108,6 +117,8 @@ const Monaco: React.FC<MonacoProps> = ({
   useEffect(() => {
     if (!divRef.current || editorRef.current) return;
     let disposer: Promise<() => void>;
+    let clearDisposer: Promise<() => void>;
+    let recompiledDisposer: Promise<() => void>;
     const timer = setTimeout(() => {
       editorRef.current = monaco.editor.create(divRef.current!, {
         accessibilitySupport: "off",
@@ -127,9 +138,16 @@ const Monaco: React.FC<MonacoProps> = ({
         ({ payload: diagnostics }) => {
           if (!editorRef.current) return;
           const model = editorRef.current.getModel();
-          if (model) {
+          if (model && file) {
             const markers: IMarkerData[] =
-              diagnostics?.map(({ range, severity, message, hints }) => {
+              diagnostics
+                ?.filter(
+                  (d) =>
+                    d.path !== null &&
+                    path.join(window.nole.workspace(), d.path) ===
+                      path.join(file.path)
+                )
+                .map(({ range, severity, message, hints }) => {
                 const start = model.getPositionAt(range.start);
                 const end = model.getPositionAt(range.end);
                 return {
@@ -151,6 +169,16 @@ const Monaco: React.FC<MonacoProps> = ({
           }
         }
       );
+      // remove all markers
+      clearDisposer = listen("typst::diagnostics_cleared", () => {
+        const model = editorRef.current?.getModel();
+        if (model) monaco.editor.setModelMarkers(model, "owner", []);
+      });
+      // compiled again after a dependency changed on disk
+      recompiledDisposer = listen<TypstCompileResult>(
+        "typst::recompiled",
+        ({ payload: document }) => showCompiled(document)
+      );
       // editorRef.current.onDidCompositionEnd(() => { }); // TODO: handle IME
       editorRef.current.onDidChangeModelContent(() => {
         setDebounceCancelFn(() => compileHandler());
@@ -172,6 +200,8 @@ const Monaco: React.FC<MonacoProps> = ({
       editorRef.current?.getModel()?.dispose();
       editorRef.current ? editorRef.current.dispose() : clearTimeout(timer);
       disposer?.then((dispose) => dispose());
+      clearDisposer?.then((dispose) => dispose());
+      recompiledDisposer?.then((dispose) => dispose());
       debounceCancelFn?.();
     };
   }, []);
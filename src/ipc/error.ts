@@ -0,0 +1,30 @@
+import { TypstDiagnostic } from "./typst";
+
+export type NoleErrorKind =
+  | "lock_poisoned"
+  | "world_not_initialized"
+  | "document_not_initialized"
+  | "page_out_of_range"
+  | "superseded"
+  | "io"
+  | "package"
+  | "font"
+  | "compile_failed"
+  | "other";
+
+/** The error every command rejects with. */
+export interface NoleError {
+  kind: NoleErrorKind;
+  message: string;
+  /** The errors of the document, set for "compile_failed". */
+  diagnostics?: TypstDiagnostic[];
+}
+
+export function isNoleError(error: unknown, kind?: NoleErrorKind): error is NoleError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    "kind" in error &&
+    (kind === undefined || (error as NoleError).kind === kind)
+  );
+}
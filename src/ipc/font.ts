@@ -0,0 +1,79 @@
+import { invoke } from "@tauri-apps/api";
+
+export type FontStyle = "normal" | "italic" | "oblique";
+
+export type FontSource =
+  | { kind: "file"; path: string; index: number }
+  | { kind: "embedded" };
+
+export interface FontVariant {
+  style: FontStyle;
+  /** From 100 (thin) to 900 (black), 400 is regular. */
+  weight: number;
+  /** The width relative to the normal width, from 0.5 to 2.0. */
+  stretch: number;
+  monospace: boolean;
+  serif: boolean;
+  math: boolean;
+  variable: boolean;
+  /** The scripts the font has glyphs for, e.g. "latin" or "cjk". */
+  coverage: string[];
+  source: FontSource;
+}
+
+export interface FontFamily {
+  /** The name to use in `text(font: ..)`. */
+  name: string;
+  variants: FontVariant[];
+}
+
+export interface FontStats {
+  /** The number of discovered fonts. */
+  total: number;
+  /** The number of fonts currently loaded from files. */
+  loaded: number;
+  /** The number of fonts embedded in the binary, which are always loaded. */
+  embedded: number;
+  /** How many fonts stay loaded from files. */
+  capacity: number;
+  /** Memory-mapped font files, each path stays mapped until the app exits. */
+  mapped_files: number;
+  /** The size of the memory-mapped font files, only the parts read are in memory. */
+  mapped_bytes: number;
+  hits: number;
+  loads: number;
+  evictions: number;
+}
+
+/**
+ * Change the global font directories.
+ * The `fonts` directory of a workspace always takes precedence over them.
+ */
+export const setFontDirs = async (dirs: string[]): Promise<void> => {
+  return invoke("set_font_dirs", { dirs: dirs });
+};
+
+/**
+ * Search the fonts again, e.g. after installing a font.
+ * The open document is compiled again and emitted as "typst::recompiled".
+ * @returns The number of fonts found.
+ */
+export const reloadFonts = async (
+  workspace: string | null = null
+): Promise<number> => {
+  return invoke("reload_fonts", { workspace: workspace });
+};
+
+/**
+ * List the discovered font families, sorted by name.
+ * With a workspace, the fonts of its `fonts` directory are included.
+ */
+export const listFonts = async (
+  workspace: string | null = null
+): Promise<FontFamily[]> => {
+  return invoke("list_fonts", { workspace: workspace });
+};
+
+export const fontStats = async (): Promise<FontStats> => {
+  return invoke("font_stats");
+};
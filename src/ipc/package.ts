@@ -0,0 +1,40 @@
+import { invoke } from "@tauri-apps/api";
+
+export type PackageState = "downloading" | "unpacking" | "done" | "failed";
+
+export interface PackageProgress {
+  spec: string;
+  state: PackageState;
+  downloaded: number;
+  total: number | null;
+}
+
+export interface PackageInfo {
+  namespace: string;
+  name: string;
+  version: string;
+  path: string;
+}
+
+export const listPackages = async (
+  workspace: string | null = null
+): Promise<PackageInfo[]> => {
+  return invoke("list_packages", { workspace: workspace });
+};
+
+/**
+ * Download a package from the registry.
+ * @param spec A package spec like `@preview/example:0.1.0`.
+ * @returns The directory the package was unpacked into.
+ */
+export const installPackage = async (spec: string): Promise<string> => {
+  return invoke("install_package", { spec: spec });
+};
+
+export const removePackage = async (spec: string): Promise<void> => {
+  return invoke("remove_package", { spec: spec });
+};
+
+export const setPackageRegistry = async (url: string): Promise<void> => {
+  return invoke("set_package_registry", { url: url });
+};
@@ -1,12 +1,25 @@
 import { invoke } from "@tauri-apps/api";
+import { NoleError } from "./error";
 
 export type TypstDiagnosticSeverity = "error" | "warning";
 
-export interface TypstDiagnostic {
+export interface TypstLocation {
+  /** Workspace-relative path, prefixed with the package spec for package files. */
+  path: string | null;
   range: { start: number; end: number };
+  line: number;
+  column: number;
+}
+
+export interface TypstTracepoint extends TypstLocation {
+  message: string;
+}
+
+export interface TypstDiagnostic extends TypstLocation {
   severity: TypstDiagnosticSeverity;
   message: string;
   hints: string[];
+  trace: TypstTracepoint[];
 }
 
 export interface TypstRenderResult {
@@ -15,11 +28,37 @@ export interface TypstRenderResult {
   height: number;
 }
 
+export interface TypstPage {
+  width: number;
+  height: number;
+  /** One-based page number. */
+  number: number;
+  /** The page counter formatted with the page's numbering, e.g. "iii". */
+  label: string;
+}
+
+/** Text that rendered as boxes or with a fallback font. */
+export interface TypstFontIssue extends TypstLocation {
+  /** "missing" glyphs render as boxes, "fallback" glyphs came from another font. */
+  kind: "missing" | "fallback";
+  text: string;
+  /** The family the text was rendered with. */
+  font: string;
+  /** The family the rest of the text was rendered with, for fallbacks. */
+  requested: string | null;
+  /** One-based page number. */
+  page: number;
+}
+
 export interface TypstCompileResult {
   updated_idx: number[];
   n_pages: number;
   width: number;
   height: number;
+  pages: TypstPage[];
+  warnings: TypstDiagnostic[];
+  /** Newer compile requests have larger generations. */
+  generation: number;
 }
 
 export enum TypstCompletionKind {
@@ -60,6 +99,50 @@ export const compile = async (
   });
 };
 
+export const setOverlay = async (
+  path: string,
+  content: string
+): Promise<void> => {
+  return invoke("set_overlay", { path: path, content: content });
+};
+
+export const clearOverlay = async (path: string | null = null): Promise<void> => {
+  return invoke("clear_overlay", { path: path });
+};
+
+export const setEntry = async (
+  workspace: string,
+  path: string | null
+): Promise<void> => {
+  return invoke("set_entry", { workspace: workspace, path: path });
+};
+
+/**
+ * Recompile when a file used by the document changes on disk. The result is
+ * emitted as "typst::recompiled", changes as "typst::dependency_changed".
+ */
+export const setAutoRecompile = async (enabled: boolean): Promise<void> => {
+  return invoke("set_auto_recompile", { enabled: enabled });
+};
+
+export interface TypstDependency {
+  /** Workspace-relative path, prefixed with the package spec for package files. */
+  path: string;
+  system_path: string | null;
+}
+
+export interface TypstDependencies {
+  sources: TypstDependency[];
+  images: TypstDependency[];
+  data: TypstDependency[];
+  packages: string[];
+}
+
+/** Get the files used by the last compilation. */
+export const dependencies = async (): Promise<TypstDependencies> => {
+  return invoke("dependencies", {});
+};
+
 export const svg = async (
   page: number,
 ): Promise<string> => {
@@ -73,18 +156,154 @@ export const render = async (
   return invoke("render", { page: page, scale: scale });
 };
 
+export type TypstJump =
+  | ({ kind: "source" } & TypstLocation)
+  | { kind: "url"; url: string }
+  /** A position in the document, `page` is zero-based and `x`, `y` are in pt. */
+  | { kind: "position"; page: number; x: number; y: number };
+
+/**
+ * Find what was clicked in the preview.
+ * @param page The zero-based page index.
+ * @param x The distance from the left of the page in pt.
+ * @param y The distance from the top of the page in pt.
+ * @returns The source of the clicked text, or the target of a clicked link.
+ */
+export const jump = async (
+  page: number,
+  x: number,
+  y: number
+): Promise<TypstJump | null> => {
+  return invoke("jump", { page: page, x: x, y: y });
+};
+
+/** Find the text of the last compilation that rendered as boxes or with a fallback font. */
+export const fontIssues = async (): Promise<TypstFontIssue[]> => {
+  return invoke("font_issues", {});
+};
+
+/**
+ * Complete the code at an offset of the content.
+ * @param path The file the content belongs to.
+ */
 export const autocomplete = async (
+  path: string,
   content: string,
   offset: number,
   explicit: boolean
 ): Promise<TypstCompleteResponse> => {
   return invoke("autocomplete", {
+    path: path,
     content: content,
     offset: offset,
     explicit: explicit,
   });
 };
 
-export const exportPDF = async (id: string, path: string): Promise<void> => {
-  return invoke("export", { id: id, path: path });
+export interface TypstHoverResult {
+  kind: "text" | "code";
+  /** Markdown for text, typst code for code. */
+  content: string;
+}
+
+/**
+ * Get the tooltip at an offset of the content, null if there is nothing to show.
+ * @param path The file the content belongs to.
+ */
+export const hover = async (
+  path: string,
+  content: string,
+  offset: number
+): Promise<TypstHoverResult | null> => {
+  return invoke("hover", {
+    path: path,
+    content: content,
+    offset: offset,
+  });
+};
+
+/**
+ * Compile a file from disk and export it as pdf.
+ * @param input The file to export, the entry of the open workspace if null.
+ */
+export const exportPDF = async (
+  id: string,
+  path: string,
+  workspace: string | null = null,
+  input: string | null = null
+): Promise<void> => {
+  return invoke("export", {
+    id: id,
+    path: path,
+    workspace: workspace,
+    input: input,
+  });
+};
+
+export type ExportFormat = "pdf" | "png" | "svg";
+
+/**
+ * Compile a file from disk and export pages of it as images.
+ * @param format "png" or "svg".
+ * @param dir The directory the images are written into.
+ * @param pages Page ranges like "1-3,7", all pages if null.
+ * @param template File names like "{stem}-{page}.png", supports {stem}, {page} and {total}.
+ * @param ppi Pixels per inch of png images.
+ * @param input The file to export, the entry of the open workspace if null.
+ * @returns The paths of the written images.
+ */
+export const exportImages = async (
+  format: ExportFormat,
+  dir: string,
+  pages: string | null = null,
+  template: string | null = null,
+  ppi: number | null = null,
+  workspace: string | null = null,
+  input: string | null = null
+): Promise<string[]> => {
+  return invoke("export_images", {
+    format: format,
+    dir: dir,
+    pages: pages,
+    template: template,
+    ppi: ppi,
+    workspace: workspace,
+    input: input,
+  });
+};
+
+export interface BatchProgress {
+  /** Workspace-relative path of the exported file. */
+  path: string;
+  output: string | null;
+  state: "done" | "failed";
+  error: NoleError | null;
+  finished: number;
+  total: number;
+}
+
+export interface BatchSummary {
+  exported: string[];
+  failed: string[];
+}
+
+/**
+ * Export every matching file of the workspace as pdf.
+ * Listen to "typst::batch_export" for the progress of each file.
+ * @param outDir Where the pdfs are written, next to their sources if null.
+ * @param include Globs of the files to export, all .typ files if null.
+ * @param exclude Globs of the files to skip.
+ */
+export const exportWorkspace = async (
+  workspace: string,
+  outDir: string | null = null,
+  include: string[] | null = null,
+  exclude: string[] | null = null
+): Promise<BatchSummary> => {
+  return invoke("export_workspace", {
+    workspace: workspace,
+    outDir: outDir,
+    include: include,
+    exclude: exclude,
+  });
 };
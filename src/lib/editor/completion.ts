@@ -15,6 +15,7 @@ export class TypstCompletionProvider implements languages.CompletionItemProvider
     _: CancellationToken
   ): Promise<languages.CompletionList> {
     const { offset: completionOffset, completions } = await autocomplete(
+      model.uri.fsPath,
       model.getValue(),
       model.getOffsetAt(position),
       context.triggerKind === CompletionTriggerKind.Invoke
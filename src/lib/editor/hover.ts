@@ -0,0 +1,34 @@
+import type { CancellationToken, editor, Position } from "monaco-editor";
+import { languages } from "monaco-editor";
+
+import { hover } from "../../ipc/typst";
+
+export class TypstHoverProvider implements languages.HoverProvider {
+  async provideHover(
+    model: editor.ITextModel,
+    position: Position,
+    _: CancellationToken
+  ): Promise<languages.Hover | null> {
+    const tooltip = await hover(
+      model.uri.fsPath,
+      model.getValue(),
+      model.getOffsetAt(position)
+    ).catch(() => null);
+    if (!tooltip) return null;
+
+    const word = model.getWordAtPosition(position);
+    const value =
+      tooltip.kind === "code" ? "```typst\n" + tooltip.content + "\n```" : tooltip.content;
+    return {
+      contents: [{ value }],
+      range: word
+        ? {
+            startLineNumber: position.lineNumber,
+            startColumn: word.startColumn,
+            endLineNumber: position.lineNumber,
+            endColumn: word.endColumn,
+          }
+        : undefined,
+    };
+  }
+}
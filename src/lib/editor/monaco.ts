@@ -10,6 +10,7 @@ import typstTm from "./lang/typst-tm.json";
 import theme from "./theme/theme.json";
 
 import { TypstCompletionProvider } from "./completion";
+import { TypstHoverProvider } from "./hover";
 
 type IMonarchLanguage = monaco.languages.IMonarchLanguage;
 
@@ -45,6 +46,7 @@ export const initMonaco = (async () => {
 
   // Register completion providers
   monaco.languages.registerCompletionItemProvider("typst", new TypstCompletionProvider());
+  monaco.languages.registerHoverProvider("typst", new TypstHoverProvider());
 
   monaco.editor.defineTheme("dracula", theme as monaco.editor.IStandaloneThemeData);
   monaco.editor.setTheme("dracula");